@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pngme::Png;
+
+// Every input must either parse or produce an error, and a parsed file must
+// serialize back to exactly the bytes it was read from
+fuzz_target!(|data: &[u8]| {
+    if let Ok(png) = Png::try_from(data) {
+        assert_eq!(png.as_bytes(), data);
+    }
+});
@@ -0,0 +1,318 @@
+use std::path::PathBuf;
+use clap::{ArgEnum, Subcommand, Args, AppSettings};
+use pngme::crypto::Recipient;
+use pngme::payload::Compression;
+use pngme::png::ChunkPosition;
+use pngme::sign::SignerKey;
+use pngme::text::TextKind;
+
+/// How command results and errors are written
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+/// What a generated key is used for
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyKind {
+    /// X25519 key to encrypt messages to with --recipient
+    Encryption,
+    /// Ed25519 key to sign messages with --sign
+    Signing,
+}
+
+#[derive(Subcommand)]
+pub enum MainArgs {
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Encode(EncodingArgs),
+
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Decode(DecodingArgs),
+
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Remove(RemovingArgs),
+
+    /// Replace the message in an existing chunk, keeping its position
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Update(UpdatingArgs),
+
+    /// List every chunk in a file
+    #[clap(setting(AppSettings::ArgRequiredElseHelp), visible_alias = "list")]
+    Print(PrintingArgs),
+
+    /// Validate chunk ordering and CRCs against the PNG spec
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Check(CheckingArgs),
+
+    /// Read and write tEXt, zTXt and iTXt metadata
+    #[clap(subcommand)]
+    Text(TextArgs),
+
+    /// Check the signature of a chunk against trusted public keys
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Verify(VerifyingArgs),
+
+    /// Generate an encryption or signing key file and print its public key
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Keygen(KeygenArgs),
+}
+
+#[derive(Subcommand)]
+pub enum TextArgs {
+    /// Store a text entry, replacing existing entries with the same keyword
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Set(TextSettingArgs),
+
+    /// Show the first text entry with a keyword
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Get(TextGettingArgs),
+
+    /// Show all text entries
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    List(TextListingArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct TextSettingArgs {
+    /// PNG file to read, or - for standard input
+    pub path: PathBuf,
+
+    pub keyword: String,
+
+    pub text: String,
+
+    /// Where to write the changed PNG, or - for standard output
+    pub output: Option<PathBuf>,
+
+    /// Chunk to store the entry in: text (Latin-1), ztxt (compressed Latin-1) or itxt (UTF-8)
+    #[clap(long, default_value = "text")]
+    pub kind: TextKind,
+
+    /// Language tag of an itxt entry, e.g. en-US
+    #[clap(long, default_value = "")]
+    pub language: String,
+
+    /// Keyword translated into the language of an itxt entry
+    #[clap(long, default_value = "")]
+    pub translated_keyword: String,
+
+    /// Compress the text of an itxt entry
+    #[clap(long)]
+    pub compress: bool,
+
+    /// Where to place the chunk: before-iend, before-idat, after-idat or a chunk index between IHDR and IEND
+    #[clap(long, default_value = "before-iend")]
+    pub position: ChunkPosition,
+}
+
+#[derive(Args, Debug)]
+pub struct TextGettingArgs {
+    /// PNG file to read, or - for standard input
+    pub path: PathBuf,
+
+    pub keyword: String,
+}
+
+#[derive(Args, Debug)]
+pub struct TextListingArgs {
+    pub path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct EncodingArgs {
+    /// PNG file to read, or - for standard input
+    pub path: PathBuf,
+
+    pub chunk_type: String,
+
+    /// Text to embed, left out when embedding a file with --file
+    pub message: Option<String>,
+
+    /// Where to write the changed PNG, or - for standard output. With --file it directly follows CHUNK_TYPE
+    pub output: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub payload: PayloadArgs,
+
+    /// Split the message into chunks carrying at most this many bytes each
+    #[clap(long, value_name = "BYTES")]
+    pub fragment_size: Option<usize>,
+
+    /// Where to place the chunk: before-iend, before-idat, after-idat or a chunk index between IHDR and IEND
+    #[clap(long, default_value = "before-iend")]
+    pub position: ChunkPosition,
+
+    #[clap(flatten)]
+    pub in_place: InPlaceArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct UpdatingArgs {
+    /// PNG file to read, or - for standard input
+    pub path: PathBuf,
+
+    pub chunk_type: String,
+
+    /// Text to embed, left out when embedding a file with --file
+    pub message: Option<String>,
+
+    /// Where to write the changed PNG, or - for standard output. With --file it directly follows CHUNK_TYPE
+    pub output: Option<PathBuf>,
+
+    /// Which chunk of CHUNK_TYPE to update, counting from 0
+    #[clap(long, value_name = "N")]
+    pub index: Option<usize>,
+
+    #[clap(flatten)]
+    pub payload: PayloadArgs,
+
+    #[clap(flatten)]
+    pub in_place: InPlaceArgs,
+}
+
+/// Overwriting the input file instead of writing to OUTPUT
+#[derive(Args, Debug)]
+pub struct InPlaceArgs {
+    /// Replace PATH atomically instead of writing to OUTPUT
+    #[clap(long, conflicts_with = "output")]
+    pub in_place: bool,
+
+    /// Keep a copy of PATH with this suffix appended, e.g. .bak
+    #[clap(long, requires = "in-place", value_name = "SUFFIX")]
+    pub backup: Option<String>,
+}
+
+/// How a message is turned into chunk data, shared by encode and update
+#[derive(Args, Debug)]
+pub struct PayloadArgs {
+    /// Embed this file with its name and size instead of a text MESSAGE
+    #[clap(long, value_name = "PATH")]
+    pub file: Option<PathBuf>,
+
+    /// MIME type to store with an embedded file, e.g. application/pdf
+    #[clap(long, requires = "file")]
+    pub mime: Option<String>,
+
+    /// Compress the payload before encryption: none, deflate or zstd
+    #[clap(long, default_value = "none", value_name = "METHOD")]
+    pub compress: Compression,
+
+    #[clap(flatten)]
+    pub key: KeyArgs,
+
+    /// Encrypt to a public key instead of a passphrase, may be given several times
+    #[clap(long = "recipient", short = 'r', value_name = "PUBLIC_KEY", multiple_occurrences = true, conflicts_with = "key_source")]
+    pub recipients: Vec<Recipient>,
+
+    /// Sign the chunk with the private key in a signing key file
+    #[clap(long, value_name = "KEY_FILE")]
+    pub sign: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct DecodingArgs {
+    /// PNG file to read, or - for standard input
+    pub path: PathBuf,
+
+    pub chunk_type: String,
+
+    #[clap(flatten)]
+    pub key: KeyArgs,
+
+    /// Decrypt with the private key in an identity file instead of a passphrase
+    #[clap(long, short = 'i', conflicts_with = "key_source")]
+    pub identity: Option<PathBuf>,
+
+    /// Write the decoded bytes to a file, into a directory under the embedded file name, or to standard output with -
+    #[clap(long, conflicts_with = "all")]
+    pub out: Option<PathBuf>,
+
+    /// Which chunk of CHUNK_TYPE to decode, counting from 0
+    #[clap(long, value_name = "N")]
+    pub index: Option<usize>,
+
+    /// Decode every message of CHUNK_TYPE
+    #[clap(long, conflicts_with = "index")]
+    pub all: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct KeygenArgs {
+    /// Where to write the key file, which must not exist yet
+    pub output: PathBuf,
+
+    #[clap(long, arg_enum, default_value = "encryption")]
+    pub kind: KeyKind,
+}
+
+#[derive(Args, Debug)]
+pub struct VerifyingArgs {
+    /// PNG file to read, or - for standard input
+    pub path: PathBuf,
+
+    pub chunk_type: String,
+
+    /// Which chunk of CHUNK_TYPE to verify, counting from 0
+    #[clap(long, value_name = "N")]
+    pub index: Option<usize>,
+
+    /// Public key the signature must be made with, may be given several times
+    #[clap(long = "trusted-key", short = 't', value_name = "PUBLIC_KEY", required = true, multiple_occurrences = true)]
+    pub trusted_keys: Vec<SignerKey>,
+}
+
+#[derive(Args, Debug)]
+pub struct PrintingArgs {
+    pub path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct CheckingArgs {
+    pub path: PathBuf,
+}
+
+#[derive(Args,Debug)]
+pub struct RemovingArgs {
+    /// PNG file to read, or - for standard input
+    pub path: PathBuf,
+
+    pub chunk_type: String,
+
+    /// Where to write the changed PNG, or - for standard output
+    pub output: Option<PathBuf>,
+
+    /// Which chunk of CHUNK_TYPE to remove, counting from 0. For a split message every fragment goes
+    #[clap(long, value_name = "N")]
+    pub index: Option<usize>,
+
+    /// Remove every chunk of CHUNK_TYPE
+    #[clap(long, conflicts_with = "index")]
+    pub all: bool,
+
+    #[clap(flatten)]
+    pub in_place: InPlaceArgs,
+}
+
+/// Where to read the passphrase from; at most one source may be given.
+///
+/// The passphrase itself is never accepted on the command line, so it cannot
+/// end up in shell history or process listings.
+#[derive(Args, Debug)]
+pub struct KeyArgs {
+    /// Prompt for the passphrase on the terminal without echoing it
+    #[clap(long, group = "key_source")]
+    pub prompt: bool,
+
+    /// Read the passphrase from the first line of a file
+    #[clap(long, group = "key_source")]
+    pub key_file: Option<PathBuf>,
+
+    /// Read the passphrase from an environment variable
+    #[clap(long, group = "key_source", value_name = "VAR")]
+    pub key_env: Option<String>,
+
+    /// Read the passphrase from an open file descriptor, e.g. 3 with `3<secret`
+    #[clap(long, group = "key_source", value_name = "FD")]
+    pub key_fd: Option<i32>,
+}
@@ -0,0 +1,929 @@
+use crate::args::{
+    CheckingArgs, DecodingArgs, EncodingArgs, InPlaceArgs, KeyKind, KeygenArgs, PayloadArgs, PrintingArgs, RemovingArgs, TextGettingArgs, TextListingArgs,
+    TextSettingArgs, UpdatingArgs, VerifyingArgs,
+};
+use crate::key::{self, Confirm};
+use crate::report::{
+    CheckReport, ChunkReport, DecodeReport, DecodedMessage, EncodeReport, KeygenReport, PrintReport, RemoveReport, TextListReport, TextReport,
+    TextSetReport, UpdateReport, VerifyReport,
+};
+use pngme::chunk::Chunk;
+use pngme::chunk_type::ChunkType;
+use pngme::crypto::{CryptoError, Identity};
+use pngme::error::PNGError;
+use pngme::fragment::{self, FragmentError};
+use pngme::ihdr::Ihdr;
+use pngme::message::{self, Decryption, EmbedOptions, Embedded, Encryption, MessageError};
+use pngme::payload::{FileInfo, Payload, PayloadError};
+use pngme::png::Png;
+use pngme::view::{ChunkRef, ChunkRefs, PngRef};
+use pngme::sign::{SignatureError, SigningIdentity};
+use pngme::text::{TextEntry, TextKind};
+use pngme::validate::{self, Violation};
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, bail, Context, Result};
+use memmap2::Mmap;
+use std::{fmt, fs, io};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::str::FromStr;
+
+/// Exit code for failures that have no more specific code
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit code when reading or writing a file failed
+pub const EXIT_IO: i32 = 3;
+/// Exit code when the input is not a valid PNG or chunk type
+pub const EXIT_PARSE: i32 = 4;
+/// Exit code when the requested chunk does not exist
+pub const EXIT_NOT_FOUND: i32 = 5;
+/// Exit code when a message could not be decrypted
+pub const EXIT_DECRYPTION: i32 = 6;
+/// Exit code when `check` found the file to break the PNG spec
+pub const EXIT_INVALID: i32 = 7;
+/// Exit code when a signature is missing, invalid or made by an untrusted key
+pub const EXIT_UNVERIFIED: i32 = 8;
+
+/// Command failures that map to a dedicated exit code
+#[derive(Debug)]
+pub enum CommandError {
+    ChunkNotFound { path: PathBuf, chunk_type: String },
+    TextNotFound { path: PathBuf, keyword: String },
+    IndexOutOfRange { path: PathBuf, chunk_type: String, index: usize, count: usize },
+}
+
+impl std::error::Error for CommandError {}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::ChunkNotFound { path, chunk_type } => {
+                write!(f, "No {} chunk found in {}", chunk_type, path.display())
+            }
+            CommandError::TextNotFound { path, keyword } => {
+                write!(f, "No text entry with keyword {:?} found in {}", keyword, path.display())
+            }
+            CommandError::IndexOutOfRange { path, chunk_type, index, count } => {
+                write!(f, "No {} chunk with index {} in {}, it has {}", chunk_type, index, path.display(), count)
+            }
+        }
+    }
+}
+
+/// Picks the exit code for an error by looking at every cause in its chain
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    for cause in err.chain() {
+        if cause.is::<io::Error>() || matches!(cause.downcast_ref::<PNGError>(), Some(PNGError::Io { .. })) {
+            return EXIT_IO;
+        }
+        // The file parsed fine, there is just no room for the chunk where it was asked to go
+        if let Some(
+            PNGError::InvalidIndex { .. } | PNGError::MissingChunk(_) | PNGError::BeforeIhdr { .. } | PNGError::AfterIend { .. },
+        ) = cause.downcast_ref::<PNGError>()
+        {
+            return EXIT_FAILURE;
+        }
+        if cause.is::<PNGError>() || cause.is::<PayloadError>() || cause.is::<FragmentError>() {
+            return EXIT_PARSE;
+        }
+        if cause.is::<CryptoError>() {
+            return EXIT_DECRYPTION;
+        }
+        if cause.is::<SignatureError>() {
+            return EXIT_UNVERIFIED;
+        }
+        if let Some(e) = cause.downcast_ref::<MessageError>() {
+            return match e {
+                MessageError::Payload(_) | MessageError::Fragment(_) => EXIT_PARSE,
+                MessageError::Crypto(_) => EXIT_DECRYPTION,
+                MessageError::IndexOutOfRange(_) => EXIT_NOT_FOUND,
+                MessageError::Encrypted | MessageError::EncryptedToRecipients | MessageError::Placement(_) => EXIT_FAILURE,
+            };
+        }
+        if let Some(e) = cause.downcast_ref::<CommandError>() {
+            return match e {
+                CommandError::ChunkNotFound { .. }
+                | CommandError::TextNotFound { .. }
+                | CommandError::IndexOutOfRange { .. } => EXIT_NOT_FOUND,
+            };
+        }
+    }
+    EXIT_FAILURE
+}
+
+pub fn encode(args: &EncodingArgs) -> Result<EncodeReport> {
+    let mut png = load_png(&args.path)?;
+    let chunk_type = ChunkType::from_str(&args.chunk_type)
+        .with_context(|| format!("Invalid chunk type {:?}", args.chunk_type))?;
+    let (message, output) = message_and_output(args.message.as_deref(), args.output.as_deref(), &args.payload)?;
+    let data = build_data(message, &args.payload)?;
+    let signer = match &args.payload.sign {
+        Some(path) => Some(load_signing_identity(path)?),
+        None => None,
+    };
+    let options = EmbedOptions {
+        position: args.position,
+        fragment_size: args.fragment_size,
+        signer: signer.as_ref(),
+    };
+    let Embedded { index, fragments } = message::embed(&mut png, &chunk_type, &data, &options)
+        .with_context(|| format!("Could not insert {} chunk into {}", args.chunk_type, args.path.display()))?;
+    let written = save(&png, &args.path, output, &args.in_place)?;
+    Ok(EncodeReport {
+        path: args.path.clone(),
+        chunk_type: args.chunk_type.clone(),
+        index,
+        fragments,
+        output: written.output,
+        backup: written.backup,
+        chunks: ChunkReport::from_png(&png),
+    })
+}
+
+pub fn update(args: &UpdatingArgs) -> Result<UpdateReport> {
+    let mut png = load_png(&args.path)?;
+    let positions = png.positions_of(&args.chunk_type);
+    if positions.is_empty() {
+        return Err(chunk_not_found(&args.path, &args.chunk_type));
+    }
+    let ordinal = select(&args.path, &args.chunk_type, positions.len(), args.index)?;
+    let index = positions[ordinal];
+    if fragment::is_fragment(png.chunks()[index].data()) {
+        bail!(
+            "{} chunk {} in {} is part of a split message, remove it with --all and encode it again",
+            args.chunk_type,
+            ordinal,
+            args.path.display()
+        );
+    }
+
+    let (message, output) = message_and_output(args.message.as_deref(), args.output.as_deref(), &args.payload)?;
+    let data = build_data(message, &args.payload)?;
+    if data.len() > Chunk::MAX_LENGTH as usize {
+        bail!("The message does not fit in a single chunk, remove it and encode it with --fragment-size");
+    }
+    let chunk = Chunk::new(ChunkType::from_str(&args.chunk_type)?, data);
+    let signer = match &args.payload.sign {
+        Some(path) => Some(load_signing_identity(path)?),
+        None => None,
+    };
+    let signature_removed = message::replace(&mut png, index, chunk, signer.as_ref())
+        .with_context(|| format!("Could not update {} chunk in {}", args.chunk_type, args.path.display()))?;
+
+    let written = save(&png, &args.path, output, &args.in_place)?;
+    Ok(UpdateReport {
+        path: args.path.clone(),
+        chunk_type: args.chunk_type.clone(),
+        index: ordinal,
+        signature_removed,
+        output: written.output,
+        backup: written.backup,
+    })
+}
+
+/// Sorts out the positionals after CHUNK_TYPE: with --file there is no MESSAGE, so the first is the OUTPUT
+fn message_and_output<'a>(
+    message: Option<&'a str>,
+    output: Option<&'a Path>,
+    payload: &PayloadArgs,
+) -> Result<(Option<&'a str>, Option<&'a Path>)> {
+    match (&payload.file, message, output) {
+        (Some(_), Some(_), Some(_)) => bail!("--file replaces MESSAGE, give at most an OUTPUT after CHUNK_TYPE"),
+        (Some(_), shifted, output) => Ok((None, shifted.map(Path::new).or(output))),
+        (None, Some(message), output) => Ok((Some(message), output)),
+        (None, None, _) => bail!("Give a MESSAGE to embed, or a file with --file"),
+    }
+}
+
+/// Turns a message or the file given with --file into chunk data, reading the key if it is to be encrypted
+fn build_data(message: Option<&str>, args: &PayloadArgs) -> Result<Vec<u8>> {
+    let payload = if let Some(path) = &args.file {
+        let data = fs::read(path).with_context(|| format!("Could not read {}", path.display()))?;
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow!("{} has no file name that can be stored", path.display()))?;
+        Payload::file(name, args.mime.as_deref(), data)
+    } else {
+        Payload::text(message.unwrap_or_default())
+    };
+    let key = match args.recipients.is_empty() {
+        true => key::read_key(&args.key, Confirm::Yes)?,
+        false => None,
+    };
+    let encryption = match &key {
+        _ if !args.recipients.is_empty() => Encryption::Recipients(&args.recipients),
+        Some(key) => Encryption::Passphrase(key),
+        None => Encryption::None,
+    };
+    message::seal(&payload.with_compression(args.compress), &encryption).context("Could not build message")
+}
+
+pub fn decode(args: &DecodingArgs) -> Result<DecodeReport> {
+    let input = Input::open(&args.path)?;
+    let png = PngRef::try_from(&input[..]).with_context(|| format!("Could not parse {} as PNG", args.path.display()))?;
+    let chunks = png
+        .chunks_by_type(&args.chunk_type)
+        .map(copy_checked)
+        .collect::<Result<Vec<Chunk>, PNGError>>()
+        .with_context(|| format!("Could not read {} chunks from {}", args.chunk_type, args.path.display()))?;
+    if chunks.is_empty() {
+        return Err(chunk_not_found(&args.path, &args.chunk_type));
+    }
+    let selected = match args.all {
+        true => message::message_starts(&chunks),
+        false => vec![select(&args.path, &args.chunk_type, chunks.len(), args.index)?],
+    };
+    // Read the key once, a prompt should not repeat for every message
+    let identity = args.identity.as_deref().map(load_identity).transpose()?;
+    let key = match identity {
+        Some(_) => None,
+        None => key::read_key(&args.key, Confirm::No)?,
+    };
+    let decryption = match (&identity, &key) {
+        (Some(identity), _) => Decryption::Identity(identity),
+        (None, Some(key)) => Decryption::Passphrase(key),
+        (None, None) => Decryption::None,
+    };
+    let messages = selected
+        .into_iter()
+        .map(|index| decode_message(args, &chunks, index, &decryption))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(DecodeReport {
+        path: args.path.clone(),
+        chunk_type: args.chunk_type.clone(),
+        messages,
+    })
+}
+
+/// Decodes the message starting at `chunks[index]`, reassembling it first if it was split
+fn decode_message(args: &DecodingArgs, chunks: &[Chunk], index: usize, decryption: &Decryption) -> Result<DecodedMessage> {
+    let describe = || format!("{} chunk {} in {}", args.chunk_type, index, args.path.display());
+    let payload = match message::extract(chunks, index).and_then(|stored| message::open(&stored, decryption)) {
+        Ok(payload) => payload,
+        Err(MessageError::Encrypted) => {
+            bail!("{} is encrypted, supply a key with --prompt, --key-file, --key-env or --key-fd", describe())
+        }
+        Err(MessageError::EncryptedToRecipients) => {
+            bail!("{} is encrypted to recipients, supply an identity with --identity", describe())
+        }
+        Err(e) => return Err(anyhow::Error::new(e).context(format!("Could not decode {}", describe()))),
+    };
+    let (message, output) = match &args.out {
+        Some(out) => {
+            let output = output_path(out, payload.file.as_ref())?;
+            write_output(&output, &payload.data)?;
+            (None, Some(output))
+        }
+        // Listing every message only reports embedded files, they are written one at a time
+        None if payload.file.is_some() && args.all => (None, None),
+        None => {
+            if let Some(file) = &payload.file {
+                bail!("{} holds the file {}, supply --out to write it", describe(), file.name)
+            }
+            let message = String::from_utf8(payload.data).map_err(|e| {
+                anyhow!("{} is not valid UTF-8, supply --out to write the raw bytes: {}", describe(), e)
+            })?;
+            (Some(message), None)
+        }
+    };
+    Ok(DecodedMessage { index, message, file: payload.file, output })
+}
+
+/// Checks `--index` against the number of chunks of the requested type, defaulting to the first
+fn select(path: &Path, chunk_type: &str, count: usize, index: Option<usize>) -> Result<usize> {
+    let index = index.unwrap_or(0);
+    if index >= count {
+        return Err(anyhow::Error::new(CommandError::IndexOutOfRange {
+            path: path.to_path_buf(),
+            chunk_type: chunk_type.to_string(),
+            index,
+            count,
+        }));
+    }
+    Ok(index)
+}
+
+pub fn remove(args: &RemovingArgs) -> Result<RemoveReport> {
+    let mut png = load_png(&args.path)?;
+    let positions = png.positions_of(&args.chunk_type);
+    if positions.is_empty() {
+        return Err(chunk_not_found(&args.path, &args.chunk_type));
+    }
+    let targets = match args.all {
+        true => positions,
+        false => {
+            // Removing a single fragment would leave the rest of its message undecodable
+            let ordinal = select(&args.path, &args.chunk_type, positions.len(), args.index)?;
+            let chunks: Vec<&[u8]> = positions.iter().map(|&index| png.chunks()[index].data()).collect();
+            message::message_chunks(&chunks, ordinal).into_iter().map(|ordinal| positions[ordinal]).collect()
+        }
+    };
+    // Back to front, so the indices of the remaining targets stay valid
+    for &index in targets.iter().rev() {
+        message::remove(&mut png, index)?;
+    }
+    let written = save(&png, &args.path, args.output.as_deref(), &args.in_place)?;
+    Ok(RemoveReport {
+        path: args.path.clone(),
+        chunk_type: args.chunk_type.clone(),
+        removed: targets.len(),
+        output: written.output,
+        backup: written.backup,
+    })
+}
+
+pub fn print(args: &PrintingArgs) -> Result<PrintReport> {
+    let input = Input::open(&args.path)?;
+    let chunks = scan(&args.path, &input)?;
+    let header = match chunks.first() {
+        Some(chunk) => Ihdr::try_from(*chunk),
+        None => Err(PNGError::MissingIhdr),
+    };
+    let (header, header_error) = match header {
+        Ok(header) => (Some(header), None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    Ok(PrintReport {
+        path: args.path.clone(),
+        header,
+        header_error,
+        chunks: ChunkReport::from_refs(&chunks),
+    })
+}
+
+pub fn text_set(args: &TextSettingArgs) -> Result<TextSetReport> {
+    let mut png = load_png(&args.path)?;
+    let entry = match args.kind {
+        TextKind::Text => TextEntry::text(&args.keyword, &args.text),
+        TextKind::Compressed => TextEntry::compressed(&args.keyword, &args.text),
+        TextKind::International => TextEntry::international(
+            &args.keyword,
+            &args.text,
+            &args.language,
+            &args.translated_keyword,
+            args.compress,
+        ),
+    };
+    let chunk = entry
+        .to_chunk()
+        .with_context(|| format!("Could not store text entry {:?}", args.keyword))?;
+    let existing: Vec<usize> = text_entries(&png)
+        .filter(|(_, entry)| entry.keyword == args.keyword)
+        .map(|(index, _)| index)
+        .collect();
+    for index in existing.iter().rev() {
+        png.remove_chunk_at(*index)?;
+    }
+    let index = png
+        .insert_chunk(chunk, args.position)
+        .with_context(|| format!("Could not insert text chunk into {}", args.path.display()))?;
+    let output = match &args.output {
+        Some(path) => {
+            write_png(path, &png)?;
+            path.clone()
+        }
+        None => bail!("No path to write supplied!"),
+    };
+    Ok(TextSetReport {
+        path: args.path.clone(),
+        keyword: args.keyword.clone(),
+        chunk_type: args.kind.chunk_type().to_string(),
+        index,
+        replaced: existing.len(),
+        output,
+    })
+}
+
+pub fn text_get(args: &TextGettingArgs) -> Result<TextReport> {
+    let png = load_png(&args.path)?;
+    let entry = text_entries(&png).find(|(_, entry)| entry.keyword == args.keyword);
+    match entry {
+        Some((_, entry)) => Ok(TextReport { path: args.path.clone(), entry }),
+        None => Err(anyhow::Error::new(CommandError::TextNotFound {
+            path: args.path.clone(),
+            keyword: args.keyword.clone(),
+        })),
+    }
+}
+
+pub fn text_list(args: &TextListingArgs) -> Result<TextListReport> {
+    let png = load_png(&args.path)?;
+    Ok(TextListReport {
+        path: args.path.clone(),
+        entries: text_entries(&png).map(|(_, entry)| entry).collect(),
+    })
+}
+
+/// All well-formed text entries of a file together with their chunk index
+fn text_entries(png: &Png) -> impl Iterator<Item = (usize, TextEntry)> + '_ {
+    png.chunks()
+        .iter()
+        .enumerate()
+        .filter_map(|(index, chunk)| TextEntry::try_from(chunk).ok().map(|entry| (index, entry)))
+}
+
+pub fn check(args: &CheckingArgs) -> Result<CheckReport> {
+    let input = Input::open(&args.path)?;
+    let chunks = scan(&args.path, &input)?;
+    let mut violations: Vec<Violation> = chunks
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| !chunk.crc_ok())
+        .map(|(index, chunk)| Violation {
+            index: Some(index),
+            chunk_type: Some(chunk.chunk_type().to_string().to_owned()),
+            message: format!("CRC mismatch, expected {:#010x}", chunk.crc()),
+        })
+        .collect();
+    violations.extend(validate::validate_refs(chunks.iter().copied()));
+    Ok(CheckReport {
+        path: args.path.clone(),
+        valid: violations.is_empty(),
+        violations,
+    })
+}
+
+pub fn verify(args: &VerifyingArgs) -> Result<VerifyReport> {
+    let png = load_png(&args.path)?;
+    let positions = png.positions_of(&args.chunk_type);
+    if positions.is_empty() {
+        return Err(chunk_not_found(&args.path, &args.chunk_type));
+    }
+    let index = positions[select(&args.path, &args.chunk_type, positions.len(), args.index)?];
+    let signer = message::verify(&png, index, &args.trusted_keys)
+        .with_context(|| format!("Could not verify {} chunk in {}", args.chunk_type, args.path.display()))?;
+    Ok(VerifyReport {
+        path: args.path.clone(),
+        chunk_type: args.chunk_type.clone(),
+        signer: signer.to_string(),
+    })
+}
+
+pub fn keygen(args: &KeygenArgs) -> Result<KeygenReport> {
+    let (public_key, secret) = match args.kind {
+        KeyKind::Encryption => {
+            let identity = Identity::generate();
+            (identity.recipient().to_string(), identity.to_secret_string())
+        }
+        KeyKind::Signing => {
+            let identity = SigningIdentity::generate();
+            (identity.signer_key().to_string(), identity.to_secret_string())
+        }
+    };
+    let contents = format!("# public key: {}\n{}\n", public_key, secret);
+    let description = match args.kind {
+        KeyKind::Encryption => "identity",
+        KeyKind::Signing => "signing key",
+    };
+    write_private(&args.output, contents.as_bytes())
+        .with_context(|| format!("Could not write {} {}", description, args.output.display()))?;
+    Ok(KeygenReport { output: args.output.clone(), public_key })
+}
+
+/// Creates a file only the current user can read, refusing to overwrite an existing one
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+/// Whether `path` is `-`, which stands for standard input or output
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// The bytes of an input file, mapped into memory when possible
+#[derive(Debug)]
+enum Input {
+    Mapped(Mmap),
+    Buffered(Vec<u8>),
+}
+
+impl Input {
+    /// Maps regular files, and reads pipes, devices and standard input (`-`) into a buffer
+    fn open(path: &Path) -> Result<Input> {
+        if is_stdio(path) {
+            return Input::read(io::stdin().lock()).context("Could not read standard input");
+        }
+        let file = fs::File::open(path).with_context(|| format!("Could not read {}", path.display()))?;
+        let metadata = file.metadata().with_context(|| format!("Could not read {}", path.display()))?;
+        // Empty files cannot be mapped on every platform
+        if metadata.is_file() && metadata.len() > 0 {
+            // SAFETY: the mapping is read-only, and pngme never writes to a file it may have mapped:
+            // `write_png` and `write_atomic` rename a new file into place instead. Like any mmap,
+            // this relies on other programs not modifying the file while pngme reads it. Nothing
+            // read through the mapping is trusted twice: print and check report the bytes once,
+            // and decode copies the chunks it uses and checks every copy, see `copy_checked`.
+            if let Ok(map) = unsafe { Mmap::map(&file) } {
+                return Ok(Input::Mapped(map));
+            }
+        }
+        Input::read(file).with_context(|| format!("Could not read {}", path.display()))
+    }
+
+    fn read(mut reader: impl Read) -> io::Result<Input> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Ok(Input::Buffered(bytes))
+    }
+}
+
+impl std::ops::Deref for Input {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Input::Mapped(map) => map,
+            Input::Buffered(bytes) => bytes,
+        }
+    }
+}
+
+/// Copies a chunk out of a possibly mapped input and checks the copy against the CRC found when parsing
+///
+/// Only the copy is used afterwards, so a file changed after parsing is an error instead of a different message.
+fn copy_checked(chunk: ChunkRef) -> Result<Chunk, PNGError> {
+    let copy = chunk.to_chunk();
+    match copy.crc() == chunk.stored_crc() {
+        true => Ok(copy),
+        false => Err(PNGError::InvalidCrc { offset: chunk.offset(), expected: chunk.stored_crc(), actual: copy.crc() }),
+    }
+}
+
+/// Walks the chunks of an input without rejecting CRC mismatches, so they can be reported
+fn scan<'a>(path: &Path, bytes: &'a [u8]) -> Result<Vec<ChunkRef<'a>>> {
+    ChunkRefs::new(bytes)
+        .and_then(|chunks| chunks.collect())
+        .with_context(|| format!("Could not parse {} as PNG", path.display()))
+}
+
+/// Writes `bytes` to a file, or to standard output when `path` is `-`
+fn write_output(path: &Path, bytes: &[u8]) -> Result<()> {
+    if is_stdio(path) {
+        let mut stdout = io::stdout().lock();
+        return stdout.write_all(bytes).and_then(|_| stdout.flush()).context("Could not write standard output");
+    }
+    fs::write(path, bytes).with_context(|| format!("Could not write {}", path.display()))
+}
+
+/// Parses a file or standard input chunk by chunk, without a copy of the raw bytes in memory
+fn load_png(path: &Path) -> Result<Png> {
+    let png = match is_stdio(path) {
+        true => Png::read_from(io::stdin().lock()),
+        false => {
+            let file = fs::File::open(path).with_context(|| format!("Could not read {}", path.display()))?;
+            Png::read_from(BufReader::new(file))
+        }
+    };
+    png.with_context(|| format!("Could not parse {} as PNG", path.display()))
+}
+
+/// Where a modified file ended up
+struct Written {
+    output: PathBuf,
+    backup: Option<PathBuf>,
+}
+
+/// Replaces `path` when editing in place, otherwise writes to `output`, which is then required
+///
+/// Without either it fails rather than quietly writing nothing, standard output has to be asked for with `-`.
+fn save(png: &Png, path: &Path, output: Option<&Path>, in_place: &InPlaceArgs) -> Result<Written> {
+    if !in_place.in_place {
+        let output = output
+            .ok_or_else(|| anyhow!("No path to write supplied, give an OUTPUT, - for standard output or --in-place!"))?;
+        write_png(output, png)?;
+        return Ok(Written { output: output.to_path_buf(), backup: None });
+    }
+    if is_stdio(path) {
+        bail!("--in-place needs a file, not standard input");
+    }
+    if let Some(output) = output {
+        bail!("--in-place replaces PATH, it cannot be combined with OUTPUT {}", output.display());
+    }
+    let backup = match &in_place.backup {
+        Some(suffix) => {
+            let backup = backup_path(path, suffix)?;
+            fs::copy(path, &backup).with_context(|| format!("Could not back up {} to {}", path.display(), backup.display()))?;
+            Some(backup)
+        }
+        None => None,
+    };
+    // Follow symlinks, so the file they point to is replaced rather than the link
+    let target = fs::canonicalize(path).with_context(|| format!("Could not resolve {}", path.display()))?;
+    write_atomic(&target, |out| png.write_to(out)).with_context(|| format!("Could not replace {}", path.display()))?;
+    Ok(Written { output: path.to_path_buf(), backup })
+}
+
+fn backup_path(path: &Path, suffix: &str) -> Result<PathBuf> {
+    if suffix.is_empty() {
+        bail!("The backup suffix must not be empty");
+    }
+    let mut name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("{} has no file name to back up under", path.display()))?
+        .to_os_string();
+    name.push(suffix);
+    Ok(path.with_file_name(name))
+}
+
+/// Replaces `path` with what `write` produces so that readers see either the old or the new file, never a partial one
+///
+/// The data goes to a temporary file in the same directory, which is synced and then renamed over `path`.
+fn write_atomic(path: &Path, write: impl FnOnce(&mut BufWriter<&fs::File>) -> io::Result<()>) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let existing = fs::metadata(path).ok();
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(".pngme-").suffix(".tmp");
+    // Temporary files are private, a new file gets the mode `File::create` would give it, 0o666 less the umask
+    #[cfg(unix)]
+    if existing.is_none() {
+        use std::os::unix::fs::PermissionsExt;
+        builder.permissions(fs::Permissions::from_mode(0o666));
+    }
+    let file = builder.tempfile_in(dir)?;
+    let mut out = BufWriter::new(file.as_file());
+    write(&mut out)?;
+    out.flush()?;
+    drop(out);
+    // An existing file keeps its permissions
+    if let Some(metadata) = existing {
+        file.as_file().set_permissions(metadata.permissions())?;
+    }
+    file.as_file().sync_all()?;
+    file.persist(path).map_err(|e| e.error)?;
+    // Sync the directory too, otherwise the rename itself may not survive a crash
+    #[cfg(unix)]
+    fs::File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Streams `png` to a file, or to standard output when `path` is `-`
+///
+/// New and regular files are replaced with `write_atomic`, so a pngme mapping the old file meanwhile
+/// keeps seeing it unchanged. Links, devices and pipes are written to directly.
+fn write_png(path: &Path, png: &Png) -> Result<()> {
+    if is_stdio(path) {
+        let mut stdout = BufWriter::new(io::stdout().lock());
+        return png.write_to(&mut stdout).context("Could not write standard output");
+    }
+    let replaceable = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata.is_file(),
+        Err(e) => e.kind() == io::ErrorKind::NotFound,
+    };
+    if replaceable {
+        return write_atomic(path, |out| png.write_to(out)).with_context(|| format!("Could not write {}", path.display()));
+    }
+    let file = fs::File::create(path).with_context(|| format!("Could not write {}", path.display()))?;
+    png.write_to(&mut BufWriter::new(file)).with_context(|| format!("Could not write {}", path.display()))
+}
+
+/// Writes into a directory under the embedded file name, otherwise to `out` itself
+fn output_path(out: &Path, file: Option<&FileInfo>) -> Result<PathBuf> {
+    match file {
+        Some(file) if out.is_dir() => {
+            // Only the last component is used, so a crafted name cannot escape the directory
+            let name = Path::new(&file.name)
+                .file_name()
+                .ok_or_else(|| anyhow!("Embedded file name {:?} cannot be used in {}", file.name, out.display()))?;
+            Ok(out.join(name))
+        }
+        _ => Ok(out.to_path_buf()),
+    }
+}
+
+fn load_identity(path: &Path) -> Result<Identity> {
+    let contents = fs::read_to_string(path).with_context(|| format!("Could not read identity {}", path.display()))?;
+    Identity::from_str(&contents).with_context(|| format!("Could not parse identity {}", path.display()))
+}
+
+fn load_signing_identity(path: &Path) -> Result<SigningIdentity> {
+    let contents = fs::read_to_string(path).with_context(|| format!("Could not read signing key {}", path.display()))?;
+    SigningIdentity::from_str(&contents).with_context(|| format!("Could not parse signing key {}", path.display()))
+}
+
+fn chunk_not_found(path: &Path, chunk_type: &str) -> anyhow::Error {
+    anyhow::Error::new(CommandError::ChunkNotFound {
+        path: path.to_path_buf(),
+        chunk_type: chunk_type.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes() {
+        let io_err = anyhow::Error::new(io::Error::from(io::ErrorKind::NotFound)).context("reading");
+        assert_eq!(exit_code(&io_err), EXIT_IO);
+
+        let parse_err = anyhow::Error::new(PNGError::InvalidChunkTypeLength(5));
+        assert_eq!(exit_code(&parse_err), EXIT_PARSE);
+
+        let placement = anyhow::Error::new(PNGError::MissingChunk("IDAT".to_string())).context("inserting");
+        assert_eq!(exit_code(&placement), EXIT_FAILURE);
+
+        let not_found = chunk_not_found(Path::new("a.png"), "ruSt");
+        assert_eq!(exit_code(&not_found), EXIT_NOT_FOUND);
+
+        let decryption = anyhow::Error::new(CryptoError::Authentication).context("decrypting");
+        assert_eq!(exit_code(&decryption), EXIT_DECRYPTION);
+
+        let unverified = anyhow::Error::new(SignatureError::Invalid).context("verifying");
+        assert_eq!(exit_code(&unverified), EXIT_UNVERIFIED);
+
+        let reassembly = anyhow::Error::new(MessageError::Fragment(FragmentError::HashMismatch)).context("decoding");
+        assert_eq!(exit_code(&reassembly), EXIT_PARSE);
+
+        assert_eq!(exit_code(&anyhow!("other")), EXIT_FAILURE);
+    }
+
+    fn encoding_args(argv: &[&str]) -> EncodingArgs {
+        use clap::{Args, FromArgMatches};
+        let matches = EncodingArgs::augment_args(clap::App::new("encode")).try_get_matches_from(argv).unwrap();
+        EncodingArgs::from_arg_matches(&matches).unwrap()
+    }
+
+    #[test]
+    fn test_file_replaces_message() {
+        let args = encoding_args(&["encode", "a.png", "ruSt", "secret.txt", "out.png"]);
+        let (message, output) = message_and_output(args.message.as_deref(), args.output.as_deref(), &args.payload).unwrap();
+        assert_eq!((message, output), (Some("secret.txt"), Some(Path::new("out.png"))));
+
+        let args = encoding_args(&["encode", "a.png", "ruSt", "--file", "secret.txt", "out.png"]);
+        let (message, output) = message_and_output(args.message.as_deref(), args.output.as_deref(), &args.payload).unwrap();
+        assert_eq!((message, output), (None, Some(Path::new("out.png"))));
+        assert_eq!(args.payload.file.as_deref(), Some(Path::new("secret.txt")));
+
+        let args = encoding_args(&["encode", "a.png", "ruSt", "--file", "secret.txt", "text", "out.png"]);
+        assert!(message_and_output(args.message.as_deref(), args.output.as_deref(), &args.payload).is_err());
+        let args = encoding_args(&["encode", "a.png", "ruSt"]);
+        assert!(message_and_output(args.message.as_deref(), args.output.as_deref(), &args.payload).is_err());
+    }
+
+    #[test]
+    fn test_encode_needs_somewhere_to_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.png");
+        write_png(&path, &Png::from_chunks(Vec::new())).unwrap();
+        let args = encoding_args(&["encode", path.to_str().unwrap(), "ruSt", "hello"]);
+        let error = encode(&args).unwrap_err();
+        assert!(error.to_string().starts_with("No path to write supplied"), "{}", error);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_remove_index_keeps_an_identical_message() {
+        use clap::{Args, FromArgMatches};
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.png");
+        let mut png = Png::from_chunks(Vec::new());
+        let options = EmbedOptions { fragment_size: Some(4), ..EmbedOptions::default() };
+        for _ in 0..2 {
+            message::embed(&mut png, &ChunkType::from_str("ruSt").unwrap(), b"same message", &options).unwrap();
+        }
+        write_png(&path, &png).unwrap();
+
+        let output = dir.path().join("removed.png");
+        let argv = ["remove", path.to_str().unwrap(), "ruSt", output.to_str().unwrap(), "--index", "0"];
+        let matches = RemovingArgs::augment_args(clap::App::new("remove")).try_get_matches_from(argv).unwrap();
+        let report = remove(&RemovingArgs::from_arg_matches(&matches).unwrap()).unwrap();
+        assert_eq!(report.removed, 3);
+
+        let png = load_png(&output).unwrap();
+        let chunks: Vec<&Chunk> = png.chunks_by_type("ruSt").collect();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(message::extract(&chunks, 0).unwrap(), b"same message");
+    }
+
+    #[test]
+    fn test_dash_means_standard_streams() {
+        assert!(is_stdio(Path::new("-")));
+        assert!(!is_stdio(Path::new("./-")));
+        assert!(!is_stdio(Path::new("-.png")));
+        assert!(!is_stdio(Path::new("")));
+    }
+
+    #[test]
+    fn test_in_place_needs_a_file() {
+        let png = Png::from_chunks(Vec::new());
+        let in_place = InPlaceArgs { in_place: true, backup: None };
+        let error = save(&png, Path::new("-"), None, &in_place).err().unwrap();
+        assert_eq!(error.to_string(), "--in-place needs a file, not standard input");
+    }
+
+    #[test]
+    fn test_missing_output_is_an_error() {
+        // Standard output is only written when asked for with -, not as a fallback
+        let png = Png::from_chunks(Vec::new());
+        let in_place = InPlaceArgs { in_place: false, backup: None };
+        let error = save(&png, Path::new("image.png"), None, &in_place).err().unwrap();
+        assert_eq!(error.to_string(), "No path to write supplied, give an OUTPUT, - for standard output or --in-place!");
+    }
+
+    #[test]
+    fn test_inputs_are_mapped_or_buffered() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.png");
+        fs::write(&path, b"contents").unwrap();
+        let mapped = Input::open(&path).unwrap();
+        assert!(matches!(mapped, Input::Mapped(_)));
+        assert_eq!(&mapped[..], b"contents");
+
+        // Empty files cannot be mapped and are read instead
+        fs::write(&path, b"").unwrap();
+        assert!(matches!(Input::open(&path).unwrap(), Input::Buffered(bytes) if bytes.is_empty()));
+    }
+
+    #[test]
+    fn test_copied_chunks_are_checked() {
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"message".to_vec());
+        let mut bytes = chunk.as_bytes();
+        assert_eq!(copy_checked(ChunkRef::parse(&bytes).unwrap()).unwrap().data(), b"message");
+
+        // Data that no longer matches the CRC read with it is not handed out
+        bytes[8] ^= 1;
+        let error = copy_checked(ChunkRef::parse(&bytes).unwrap()).unwrap_err();
+        assert!(matches!(error, PNGError::InvalidCrc { offset: 0, .. }), "{:?}", error);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_devices_and_pipes_are_buffered() {
+        assert!(matches!(Input::open(Path::new("/dev/null")).unwrap(), Input::Buffered(bytes) if bytes.is_empty()));
+
+        // A pipe is not a regular file either, a thread feeds it while it is read
+        let dir = tempfile::tempdir().unwrap();
+        let fifo = dir.path().join("fifo");
+        let created = std::process::Command::new("mkfifo").arg(&fifo).status();
+        if !created.is_ok_and(|status| status.success()) {
+            return;
+        }
+        let writer = {
+            let fifo = fifo.clone();
+            std::thread::spawn(move || fs::write(fifo, b"piped bytes").unwrap())
+        };
+        let input = Input::open(&fifo).unwrap();
+        writer.join().unwrap();
+        assert!(matches!(input, Input::Buffered(ref bytes) if bytes == b"piped bytes"));
+    }
+
+    #[test]
+    fn test_write_png_replaces_instead_of_rewriting() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.png");
+        fs::write(&path, b"old file").unwrap();
+        let old = Input::open(&path).unwrap();
+        write_png(&path, &Png::from_chunks(Vec::new())).unwrap();
+        // The mapping still shows the old file, which was replaced rather than truncated
+        assert_eq!(&old[..], b"old file");
+        assert_eq!(fs::read(&path).unwrap(), Png::STANDARD_HEADER);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.png");
+        fs::write(&path, b"old").unwrap();
+        write_atomic(&path, |out| out.write_all(b"new contents")).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new contents");
+        // Only the target is left, no temporary files
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_new_outputs_are_not_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        // A file made the usual way shows what the umask leaves of 0o666
+        let reference = dir.path().join("reference");
+        fs::File::create(&reference).unwrap();
+        let expected = fs::metadata(&reference).unwrap().permissions().mode() & 0o777;
+
+        let output = dir.path().join("image.png");
+        write_png(&output, &Png::from_chunks(Vec::new())).unwrap();
+        assert_eq!(fs::metadata(&output).unwrap().permissions().mode() & 0o777, expected);
+
+        // An existing file keeps its own mode
+        fs::set_permissions(&output, fs::Permissions::from_mode(0o640)).unwrap();
+        write_png(&output, &Png::from_chunks(Vec::new())).unwrap();
+        assert_eq!(fs::metadata(&output).unwrap().permissions().mode() & 0o777, 0o640);
+    }
+
+    #[test]
+    fn test_backup_path() {
+        assert_eq!(backup_path(Path::new("dir/image.png"), ".bak").unwrap(), Path::new("dir/image.png.bak"));
+        assert!(backup_path(Path::new("image.png"), "").is_err());
+    }
+}
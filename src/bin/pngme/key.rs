@@ -0,0 +1,147 @@
+use crate::args::KeyArgs;
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::io::Read;
+
+/// Whether a prompted passphrase has to be typed twice
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Confirm {
+    Yes,
+    No,
+}
+
+/// Reads the passphrase from the source selected in `args`, or `None` if no source was given.
+///
+/// Error messages only ever name the source, never the passphrase.
+pub fn read_key(args: &KeyArgs, confirm: Confirm) -> Result<Option<String>> {
+    let key = if args.prompt {
+        prompt(confirm)?
+    } else if let Some(path) = &args.key_file {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Could not read key file {}", path.display()))?;
+        first_line(&contents).to_string()
+    } else if let Some(var) = &args.key_env {
+        std::env::var(var).with_context(|| format!("Could not read key from environment variable {}", var))?
+    } else if let Some(fd) = args.key_fd {
+        first_line(&read_fd(fd)?).to_string()
+    } else {
+        return Ok(None);
+    };
+    if key.is_empty() {
+        bail!("The passphrase must not be empty");
+    }
+    Ok(Some(key))
+}
+
+fn prompt(confirm: Confirm) -> Result<String> {
+    let key = rpassword::prompt_password("Passphrase: ").context("Could not read passphrase from terminal")?;
+    if confirm == Confirm::Yes {
+        let again = rpassword::prompt_password("Repeat passphrase: ")
+            .context("Could not read passphrase from terminal")?;
+        if again != key {
+            bail!("The passphrases do not match");
+        }
+    }
+    Ok(key)
+}
+
+#[cfg(unix)]
+fn read_fd(fd: i32) -> Result<String> {
+    // Standard input may carry the PNG itself, and standard output and error are not ours to read
+    if fd < 3 {
+        bail!("Invalid key file descriptor {}, use 3 or above", fd);
+    }
+    // Opening /dev/fd gives us a descriptor of our own, the caller's stays open and untouched.
+    // Unlike adopting the raw number it fails cleanly when nothing is open under it.
+    let mut file = fs::File::open(format!("/dev/fd/{}", fd))
+        .with_context(|| format!("Could not open key file descriptor {}", fd))?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)
+        .with_context(|| format!("Could not read key from file descriptor {}", fd))?;
+    Ok(contents)
+}
+
+#[cfg(not(unix))]
+fn read_fd(fd: i32) -> Result<String> {
+    bail!("Reading the key from file descriptor {} is only supported on Unix", fd)
+}
+
+/// Key files and pipes usually end with a newline that is not part of the passphrase
+fn first_line(contents: &str) -> &str {
+    contents.lines().next().unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn no_source() -> KeyArgs {
+        KeyArgs { prompt: false, key_file: None, key_env: None, key_fd: None }
+    }
+
+    #[test]
+    fn test_no_source() {
+        assert_eq!(read_key(&no_source(), Confirm::No).unwrap(), None);
+    }
+
+    #[test]
+    fn test_key_file() {
+        let path = std::env::temp_dir().join(format!("pngme-key-test-{}", std::process::id()));
+        fs::write(&path, "hunter2\nignored\n").unwrap();
+        let args = KeyArgs { key_file: Some(path.clone()), ..no_source() };
+        let key = read_key(&args, Confirm::No);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(key.unwrap().as_deref(), Some("hunter2"));
+    }
+
+    #[test]
+    fn test_missing_key_file() {
+        let args = KeyArgs { key_file: Some(PathBuf::from("/nonexistent/key")), ..no_source() };
+        assert!(read_key(&args, Confirm::No).is_err());
+    }
+
+    #[test]
+    fn test_key_env() {
+        std::env::set_var("PNGME_KEY_TEST", "hunter2");
+        let args = KeyArgs { key_env: Some("PNGME_KEY_TEST".to_string()), ..no_source() };
+        assert_eq!(read_key(&args, Confirm::No).unwrap().as_deref(), Some("hunter2"));
+
+        std::env::set_var("PNGME_KEY_TEST_EMPTY", "");
+        let args = KeyArgs { key_env: Some("PNGME_KEY_TEST_EMPTY".to_string()), ..no_source() };
+        assert!(read_key(&args, Confirm::No).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_key_fd() {
+        use std::os::unix::io::AsRawFd;
+
+        for fd in [-1, 0, 1, 2] {
+            let args = KeyArgs { key_fd: Some(fd), ..no_source() };
+            assert!(read_key(&args, Confirm::No).is_err(), "fd {}", fd);
+        }
+        // Nothing is open under this number, which is an error rather than undefined behaviour
+        let args = KeyArgs { key_fd: Some(999_999), ..no_source() };
+        let error = read_key(&args, Confirm::No).unwrap_err();
+        assert_eq!(error.to_string(), "Could not open key file descriptor 999999");
+
+        let path = std::env::temp_dir().join(format!("pngme-key-fd-test-{}", std::process::id()));
+        fs::write(&path, "hunter2\n").unwrap();
+        let file = fs::File::open(&path).unwrap();
+        let args = KeyArgs { key_fd: Some(file.as_raw_fd()), ..no_source() };
+        let key = read_key(&args, Confirm::No);
+        // The descriptor is still open after reading from it
+        let still_open = file.metadata().is_ok();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(key.unwrap().as_deref(), Some("hunter2"));
+        assert!(still_open);
+    }
+
+    #[test]
+    fn test_first_line() {
+        assert_eq!(first_line("key\r\n"), "key");
+        assert_eq!(first_line("key"), "key");
+        assert_eq!(first_line(""), "");
+    }
+}
@@ -0,0 +1,133 @@
+mod args;
+mod commands;
+mod key;
+mod report;
+
+use clap::{Parser, AppSettings};
+use args::{Format, MainArgs, TextArgs};
+use report::ErrorReport;
+use serde::Serialize;
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Program to encode messages in png files
+#[derive(Parser)]
+#[clap(author, version, about, long_about = None)]
+#[clap(global_setting(AppSettings::PropagateVersion))]
+#[clap(global_setting(AppSettings::UseLongFormatForHelpSubcommand))]
+struct Program {
+    #[clap(subcommand)]
+    command: MainArgs,
+
+    /// Output format for results and errors
+    #[clap(long, global = true, arg_enum, default_value = "text")]
+    format: Format,
+}
+
+fn main() {
+    let args = Program::parse();
+
+    let format = args.format;
+
+    let result = match &args.command {
+        // With --file the OUTPUT may sit in the MESSAGE position, the report knows where it went
+        MainArgs::Encode(args) => {
+            commands::encode(args).and_then(|report| emit_beside(format, &report, Some(&report.output)))
+        }
+        MainArgs::Decode(args) => {
+            commands::decode(args).and_then(|report| emit_beside(format, &report, args.out.as_deref()))
+        }
+        MainArgs::Remove(args) => {
+            commands::remove(args).and_then(|report| emit_beside(format, &report, args.output.as_deref()))
+        }
+        MainArgs::Update(args) => {
+            commands::update(args).and_then(|report| emit_beside(format, &report, Some(&report.output)))
+        }
+        MainArgs::Print(args) => commands::print(args).and_then(|report| emit(format, &report)),
+        MainArgs::Text(TextArgs::Set(args)) => {
+            commands::text_set(args).and_then(|report| emit_beside(format, &report, args.output.as_deref()))
+        }
+        MainArgs::Text(TextArgs::Get(args)) => commands::text_get(args).and_then(|report| emit(format, &report)),
+        MainArgs::Text(TextArgs::List(args)) => commands::text_list(args).and_then(|report| emit(format, &report)),
+        MainArgs::Verify(args) => commands::verify(args).and_then(|report| emit(format, &report)),
+        MainArgs::Keygen(args) => commands::keygen(args).and_then(|report| emit(format, &report)),
+        MainArgs::Check(args) => commands::check(args).and_then(|report| {
+            emit(format, &report)?;
+            if !report.valid {
+                std::process::exit(commands::EXIT_INVALID);
+            }
+            Ok(())
+        }),
+    };
+
+    if let Err(e) = result {
+        let exit_code = commands::exit_code(&e);
+        match format {
+            Format::Text => eprintln!("Error: {:#}", e),
+            Format::Json => {
+                // Errors go to stdout as well so consumers only need to parse one stream
+                let report = ErrorReport {
+                    message: e.to_string(),
+                    causes: e.chain().skip(1).map(|cause| cause.to_string()).collect(),
+                    exit_code,
+                };
+                println!("{}", serde_json::json!({ "error": report }));
+            }
+        }
+        std::process::exit(exit_code);
+    }
+}
+
+fn emit<T: Serialize + Display>(format: Format, report: &T) -> anyhow::Result<()> {
+    write_report(&mut io::stdout().lock(), format, report)
+}
+
+/// Like `emit`, but moves the report to stderr when the command wrote its file to stdout
+fn emit_beside<T: Serialize + Display>(format: Format, report: &T, output: Option<&Path>) -> anyhow::Result<()> {
+    match report_to_stderr(output) {
+        true => write_report(&mut io::stderr().lock(), format, report),
+        false => emit(format, report),
+    }
+}
+
+/// Whether stdout is taken by the file written to `output`, so the report has to go elsewhere
+fn report_to_stderr(output: Option<&Path>) -> bool {
+    output.is_some_and(commands::is_stdio)
+}
+
+fn write_report<T: Serialize + Display>(out: &mut impl Write, format: Format, report: &T) -> anyhow::Result<()> {
+    let written = match format {
+        Format::Text => write!(out, "{}", report),
+        Format::Json => writeln!(out, "{}", serde_json::to_string(report)?),
+    };
+    match written {
+        // The reader went away, as `pngme print image.png | head` does, which is not a failure
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        written => Ok(written?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_moves_to_stderr_for_stdout_output() {
+        assert!(report_to_stderr(Some(Path::new("-"))));
+        assert!(!report_to_stderr(Some(Path::new("out.png"))));
+        assert!(!report_to_stderr(Some(Path::new("./-"))));
+        assert!(!report_to_stderr(None));
+    }
+
+    #[test]
+    fn test_write_report_formats() {
+        let report = serde_json::json!({ "removed": 1 });
+        let mut text = Vec::new();
+        write_report(&mut text, Format::Text, &report).unwrap();
+        assert_eq!(text, br#"{"removed":1}"#);
+        let mut json = Vec::new();
+        write_report(&mut json, Format::Json, &report).unwrap();
+        assert_eq!(json, b"{\"removed\":1}\n");
+    }
+}
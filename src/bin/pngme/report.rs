@@ -0,0 +1,407 @@
+use pngme::chunk::Chunk;
+use pngme::chunk_type::ChunkType;
+use pngme::ihdr::Ihdr;
+use pngme::payload::FileInfo;
+use pngme::png::Png;
+use pngme::view::ChunkRef;
+use pngme::text::TextEntry;
+use pngme::validate::Violation;
+use serde::Serialize;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Summary of a single chunk as shown by `print` and after writing a file
+#[derive(Debug, Serialize)]
+pub struct ChunkReport {
+    pub index: usize,
+    pub offset: usize,
+    pub chunk_type: String,
+    pub length: usize,
+    pub crc: u32,
+    pub crc_ok: bool,
+    pub critical: bool,
+    pub public: bool,
+    pub safe_to_copy: bool,
+}
+
+impl ChunkReport {
+    fn new(index: usize, offset: usize, chunk_type: &ChunkType, length: usize, crc: u32, crc_ok: bool) -> Self {
+        ChunkReport {
+            index,
+            offset,
+            chunk_type: chunk_type.to_string().to_owned(),
+            length,
+            crc,
+            crc_ok,
+            critical: chunk_type.is_critical(),
+            public: chunk_type.is_public(),
+            safe_to_copy: chunk_type.is_safe_to_copy(),
+        }
+    }
+
+    pub fn from_refs(chunks: &[ChunkRef]) -> Vec<ChunkReport> {
+        chunks
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| {
+                ChunkReport::new(index, chunk.offset(), &chunk.chunk_type(), chunk.length(), chunk.stored_crc(), chunk.crc_ok())
+            })
+            .collect()
+    }
+
+    /// Reports the chunks of an in-memory `Png` at the offsets they will have once written
+    pub fn from_png(png: &Png) -> Vec<ChunkReport> {
+        let mut offset = Png::STANDARD_HEADER.len();
+        let mut reports = Vec::with_capacity(png.chunks().len());
+        for (index, chunk) in png.chunks().iter().enumerate() {
+            reports.push(ChunkReport::new(index, offset, chunk.chunk_type(), chunk.length(), chunk.crc(), true));
+            offset += chunk.length() + Chunk::METADATA_LENGTH;
+        }
+        reports
+    }
+}
+
+/// Result of the `print` subcommand
+#[derive(Debug, Serialize)]
+pub struct PrintReport {
+    pub path: PathBuf,
+    /// The decoded `IHDR`, or `None` when the first chunk is missing or invalid
+    pub header: Option<Ihdr>,
+    /// Why `header` is `None`
+    pub header_error: Option<String>,
+    pub chunks: Vec<ChunkReport>,
+}
+
+impl fmt::Display for PrintReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.header, &self.header_error) {
+            (Some(header), _) => writeln!(f, "Image: {}", header)?,
+            (None, Some(error)) => writeln!(f, "Image: no valid IHDR chunk ({})", error)?,
+            (None, None) => writeln!(f, "Image: no valid IHDR chunk")?,
+        }
+        write_chunk_table(f, &self.chunks)
+    }
+}
+
+/// Result of the `encode` subcommand
+#[derive(Debug, Serialize)]
+pub struct EncodeReport {
+    pub path: PathBuf,
+    pub chunk_type: String,
+    /// Index of the first chunk written
+    pub index: usize,
+    /// Number of chunks the message was split across
+    pub fragments: usize,
+    pub output: PathBuf,
+    /// Copy of the original file kept by `--backup`
+    pub backup: Option<PathBuf>,
+    /// The chunks of the written file, only part of the JSON report
+    pub chunks: Vec<ChunkReport>,
+}
+
+impl fmt::Display for EncodeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.fragments > 1 {
+            writeln!(f, "Split the message across {} {} chunks", self.fragments, self.chunk_type)?;
+        }
+        writeln!(f, "Successfully encoded your secret message into {}!", self.output.display())?;
+        write_backup(f, &self.backup)
+    }
+}
+
+/// Result of the `decode` subcommand
+#[derive(Debug, Serialize)]
+pub struct DecodeReport {
+    pub path: PathBuf,
+    pub chunk_type: String,
+    pub messages: Vec<DecodedMessage>,
+}
+
+/// A single message found by `decode`
+#[derive(Debug, Serialize)]
+pub struct DecodedMessage {
+    /// Position among the chunks of the requested type, as used by `--index`
+    pub index: usize,
+    /// The decoded text, `None` for embedded files and when the payload was written to `output`
+    pub message: Option<String>,
+    /// Name, size and type of an embedded file
+    pub file: Option<FileInfo>,
+    pub output: Option<PathBuf>,
+}
+
+impl fmt::Display for DecodeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let listing = self.messages.len() > 1;
+        for decoded in &self.messages {
+            if listing {
+                write!(f, "[{}] ", decoded.index)?;
+            }
+            match (&decoded.message, &decoded.file, &decoded.output) {
+                (Some(message), _, _) if listing => writeln!(f, "{}", message)?,
+                (Some(message), _, _) => writeln!(f, "Your decoded message is: {}", message)?,
+                (None, Some(file), Some(output)) => {
+                    writeln!(f, "Wrote {} to {}", describe_file(file), output.display())?
+                }
+                (None, Some(file), None) => writeln!(f, "File {}", describe_file(file))?,
+                (None, None, Some(output)) => writeln!(f, "Wrote message to {}", output.display())?,
+                (None, None, None) => writeln!(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_backup(f: &mut fmt::Formatter<'_>, backup: &Option<PathBuf>) -> fmt::Result {
+    match backup {
+        Some(backup) => writeln!(f, "Kept the original as {}", backup.display()),
+        None => Ok(()),
+    }
+}
+
+fn describe_file(file: &FileInfo) -> String {
+    match &file.mime {
+        Some(mime) => format!("{} ({} bytes, {})", file.name, file.size, mime),
+        None => format!("{} ({} bytes)", file.name, file.size),
+    }
+}
+
+/// Result of the `remove` subcommand
+#[derive(Debug, Serialize)]
+pub struct RemoveReport {
+    pub path: PathBuf,
+    pub chunk_type: String,
+    /// Number of chunks removed, not counting their signatures
+    pub removed: usize,
+    pub output: PathBuf,
+    /// Copy of the original file kept by `--backup`
+    pub backup: Option<PathBuf>,
+}
+
+impl fmt::Display for RemoveReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.removed {
+            1 => writeln!(f, "Removed {} chunk and wrote {}", self.chunk_type, self.output.display())?,
+            n => writeln!(f, "Removed {} {} chunks and wrote {}", n, self.chunk_type, self.output.display())?,
+        }
+        write_backup(f, &self.backup)
+    }
+}
+
+/// Result of the `update` subcommand
+#[derive(Debug, Serialize)]
+pub struct UpdateReport {
+    pub path: PathBuf,
+    pub chunk_type: String,
+    /// Position among the chunks of `chunk_type`, as used by `--index`
+    pub index: usize,
+    /// Whether a signature of the old message was dropped because no new one was made
+    pub signature_removed: bool,
+    pub output: PathBuf,
+    /// Copy of the original file kept by `--backup`
+    pub backup: Option<PathBuf>,
+}
+
+impl fmt::Display for UpdateReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.signature_removed {
+            writeln!(f, "Removed the outdated signature, use --sign to sign the new message")?;
+        }
+        writeln!(f, "Updated {} chunk {} and wrote {}", self.chunk_type, self.index, self.output.display())?;
+        write_backup(f, &self.backup)
+    }
+}
+
+/// Result of the `keygen` subcommand
+#[derive(Debug, Serialize)]
+pub struct KeygenReport {
+    pub output: PathBuf,
+    pub public_key: String,
+}
+
+impl fmt::Display for KeygenReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Wrote private key to {}", self.output.display())?;
+        writeln!(f, "Public key: {}", self.public_key)
+    }
+}
+
+/// Result of a successful `verify` subcommand
+#[derive(Debug, Serialize)]
+pub struct VerifyReport {
+    pub path: PathBuf,
+    pub chunk_type: String,
+    pub signer: String,
+}
+
+impl fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} chunk has a valid signature by {}", self.chunk_type, self.signer)
+    }
+}
+
+/// Result of the `text set` subcommand
+#[derive(Debug, Serialize)]
+pub struct TextSetReport {
+    pub path: PathBuf,
+    pub keyword: String,
+    pub chunk_type: String,
+    pub index: usize,
+    /// Number of existing entries with the same keyword that were replaced
+    pub replaced: usize,
+    pub output: PathBuf,
+}
+
+impl fmt::Display for TextSetReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Stored {} as {}, replacing {} entr{}, and wrote {}",
+            self.keyword,
+            self.chunk_type,
+            self.replaced,
+            if self.replaced == 1 { "y" } else { "ies" },
+            self.output.display()
+        )
+    }
+}
+
+/// Result of the `text get` subcommand
+#[derive(Debug, Serialize)]
+pub struct TextReport {
+    pub path: PathBuf,
+    pub entry: TextEntry,
+}
+
+impl fmt::Display for TextReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.entry.text)
+    }
+}
+
+/// Result of the `text list` subcommand
+#[derive(Debug, Serialize)]
+pub struct TextListReport {
+    pub path: PathBuf,
+    pub entries: Vec<TextEntry>,
+}
+
+impl fmt::Display for TextListReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+/// Result of the `check` subcommand
+#[derive(Debug, Serialize)]
+pub struct CheckReport {
+    pub path: PathBuf,
+    pub valid: bool,
+    pub violations: Vec<Violation>,
+}
+
+impl fmt::Display for CheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.valid {
+            return writeln!(f, "{}: valid", self.path.display());
+        }
+        writeln!(f, "{}: {} violation(s)", self.path.display(), self.violations.len())?;
+        for violation in &self.violations {
+            writeln!(f, "  {}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+/// A failed command, reported in place of a result
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    pub message: String,
+    pub causes: Vec<String>,
+    pub exit_code: i32,
+}
+
+fn write_chunk_table(f: &mut fmt::Formatter<'_>, chunks: &[ChunkReport]) -> fmt::Result {
+    writeln!(
+        f,
+        "{:>5}  {:>10}  {:<4}  {:>10}  {:<10}  {:<3}  {:<8}  {:<8}  SAFE",
+        "INDEX", "OFFSET", "TYPE", "LENGTH", "CRC", "OK", "CRITICAL", "PUBLIC"
+    )?;
+    for chunk in chunks {
+        writeln!(
+            f,
+            "{:>5}  {:>10}  {:<4}  {:>10}  {:#010x}  {:<3}  {:<8}  {:<8}  {}",
+            chunk.index,
+            chunk.offset,
+            chunk.chunk_type,
+            chunk.length,
+            chunk.crc,
+            yes_no(chunk.crc_ok),
+            yes_no(chunk.critical),
+            yes_no(chunk.public),
+            yes_no(chunk.safe_to_copy),
+        )?;
+    }
+    Ok(())
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value { "yes" } else { "no" }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pngme::view::ChunkRefs;
+    use std::str::FromStr;
+
+    fn testing_png() -> Png {
+        let chunks = vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"message".to_vec()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ];
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_chunk_reports_match_scanned_file() {
+        let png = testing_png();
+        let from_png = ChunkReport::from_png(&png);
+        let bytes = png.as_bytes();
+        let chunks: Vec<ChunkRef> = ChunkRefs::new(&bytes).unwrap().collect::<Result<_, _>>().unwrap();
+        let from_refs = ChunkReport::from_refs(&chunks);
+
+        let offsets: Vec<usize> = from_png.iter().map(|c| c.offset).collect();
+        assert_eq!(offsets, [8, 33, 52]);
+        assert_eq!(offsets, from_refs.iter().map(|c| c.offset).collect::<Vec<usize>>());
+        assert!(from_refs.iter().all(|c| c.crc_ok));
+    }
+
+    #[test]
+    fn test_print_report_explains_missing_header() {
+        let png = testing_png();
+        let report = PrintReport {
+            path: PathBuf::from("image.png"),
+            header: None,
+            header_error: png.header().err().map(|e| e.to_string()),
+            chunks: ChunkReport::from_png(&png),
+        };
+        let text = report.to_string();
+        assert!(text.starts_with("Image: no valid IHDR chunk (Invalid IHDR chunk: "), "{}", text);
+        assert!(serde_json::to_value(&report).unwrap()["header_error"].is_string());
+    }
+
+    #[test]
+    fn test_chunk_report_json() {
+        let report = &ChunkReport::from_png(&testing_png())[1];
+        let json = serde_json::to_value(report).unwrap();
+        assert_eq!(json["index"], 1);
+        assert_eq!(json["chunk_type"], "ruSt");
+        assert_eq!(json["length"], 7);
+        assert_eq!(json["critical"], false);
+        assert_eq!(json["safe_to_copy"], true);
+    }
+}
@@ -1,10 +1,14 @@
-// use crate::Result;
 use crate::chunk_type::ChunkType;
+use crate::error::PNGError;
+use crate::view::ChunkRef;
+use crc::crc32::{self, Hasher32};
 use std::convert::TryFrom;
 use std::fmt;
+use std::io::{self, Write};
 
-use crate::{Error, Result};
+use crate::Result;
 
+/// A chunk of a PNG file, its length and CRC are derived from the type and data
 #[derive(Debug)]
 pub struct Chunk {
     data: Vec<u8>,
@@ -12,9 +16,13 @@ pub struct Chunk {
 }
 
 impl Chunk {
+    /// Bytes taken up by the length, type and CRC fields around the data
+    pub const METADATA_LENGTH: usize = 12;
+    /// Largest data length allowed by the PNG spec
+    pub const MAX_LENGTH: u32 = (1 << 31) - 1;
+
     pub fn length(&self) -> usize {
         self.data.len()
-        // self.length.try_into().unwrap()
     }
 
     pub fn chunk_type(&self) -> &ChunkType {
@@ -25,75 +33,69 @@ impl Chunk {
         &self.data
     }
 
+    /// CRC-32 over the chunk type and data, as stored after the data
     pub fn crc(&self) -> u32 {
-        let bytes: Vec<u8> = self
-            .chunk_type
-            .bytes()
-            .iter()
-            .chain(self.data.iter())
-            .copied()
-            .collect();
-        crc::crc32::checksum_ieee(&bytes)
+        let mut digest = crc32::Digest::new(crc32::IEEE);
+        digest.write(&self.chunk_type.bytes());
+        digest.write(&self.data);
+        digest.sum32()
     }
     
     pub fn data_as_string(&self) -> Result<String> {
         let data = self.data.clone();
-        Ok(String::from_utf8(data).unwrap())
+        Ok(String::from_utf8(data)?)
     }
 
+    /// Serializes the chunk: length, type, data and CRC
+    ///
+    /// Panics if the data is longer than `Chunk::MAX_LENGTH`, use `write_to` to get an error instead.
     pub fn as_bytes(&self) -> Vec<u8> {
-        let len_bytes = (self.length() as u32).to_be_bytes();
-        let bytes: Vec<u8> = len_bytes
-            .iter()
-            .chain(self.chunk_type.bytes().iter())
-            .chain(self.data.iter())
-            .chain(self.crc().to_be_bytes().iter())
-            .copied()
-            .collect();
+        let mut bytes = Vec::with_capacity(self.length() + Chunk::METADATA_LENGTH);
+        self.write_to(&mut bytes).expect("chunk data exceeds Chunk::MAX_LENGTH");
         bytes
     }
 
+    /// Writes the chunk as `as_bytes` would, without building it in memory first
+    ///
+    /// Data longer than `Chunk::MAX_LENGTH` cannot be described by the length field and is rejected.
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        let length = u32::try_from(self.length())
+            .ok()
+            .filter(|&length| length <= Chunk::MAX_LENGTH)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "chunk length exceeds 2^31 - 1"))?;
+        out.write_all(&length.to_be_bytes())?;
+        out.write_all(&self.chunk_type.bytes())?;
+        out.write_all(&self.data)?;
+        out.write_all(&self.crc().to_be_bytes())
+    }
+
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
         Chunk{chunk_type, data}
     }
 
-}
+    /// Parses a chunk without verifying its CRC, returning the CRC stored in the input alongside it
+    pub fn parse_with_crc(value: &[u8]) -> std::result::Result<(Chunk, u32), PNGError> {
+        let chunk = ChunkRef::parse(value)?;
+        Ok((chunk.to_chunk(), chunk.stored_crc()))
+    }
 
-#[derive(Debug)]
-pub enum CreateChunkError {
-    MismatchedCrc
 }
 
-impl std::error::Error for CreateChunkError {
-    
-}
-
-impl fmt::Display for CreateChunkError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match *self {
-            CreateChunkError::MismatchedCrc => {
-                write!(f, "CRC does not match calculated value!")
-            }
-        }
+/// The chunk data, so chunks can be passed where only their data is needed
+impl AsRef<[u8]> for Chunk {
+    fn as_ref(&self) -> &[u8] {
+        &self.data
     }
 }
 
 impl TryFrom<&[u8]> for Chunk {
-    type Error = Error;
-
-    fn try_from(value: &[u8]) -> Result<Self> {
-        let mut_value = value.clone();
-        let split_1 = mut_value.split_at(4);
-        let split_2 = split_1.1.split_at(4);
-        let chunk_rem: [u8; 4] = (split_2.0).try_into().expect("Could not read out chunk type!");
-        let chunk_type = ChunkType::try_from(chunk_rem).unwrap();
-        let split_3 = split_2.1.split_at(split_2.1.len() - 4);
-        let data: Vec<u8> = split_3.0.to_vec();
-        let crc: u32 = u32::from_be_bytes(split_3.1.try_into().expect("Could not read out crc!"));
-        let res = Chunk{ chunk_type, data};
+    type Error = PNGError;
+
+    fn try_from(value: &[u8]) -> std::result::Result<Self, PNGError> {
+        let (res, crc) = Chunk::parse_with_crc(value)?;
         let expected_crc = res.crc();
         if crc != expected_crc {
-            return Err(Box::from(CreateChunkError::MismatchedCrc));
+            return Err(PNGError::InvalidCrc { offset: 0, expected: expected_crc, actual: crc });
         }
         Ok(res)
     }
@@ -115,8 +117,6 @@ impl fmt::Display for Chunk {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::chunk_type::ChunkType;
-    use std::str::FromStr;
 
     fn testing_chunk() -> Chunk {
         let data_length: u32 = 42;
@@ -159,6 +159,15 @@ mod tests {
         assert_eq!(chunk_string, expected_chunk_string);
     }
 
+    #[test]
+    fn test_chunk_string_error_crosses_threads() {
+        let chunk = Chunk::new(ChunkType::try_from(*b"RuSt").unwrap(), vec![0xff, 0xfe]);
+        let error = std::thread::spawn(move || chunk.data_as_string().unwrap_err()).join().unwrap();
+        assert!(error.downcast_ref::<std::string::FromUtf8Error>().is_some());
+        let error = anyhow::anyhow!(error);
+        assert!(error.to_string().contains("utf-8"), "{}", error);
+    }
+
     #[test]
     fn test_chunk_crc() {
         let chunk = testing_chunk();
@@ -209,9 +218,48 @@ mod tests {
             .collect();
 
         let chunk = Chunk::try_from(chunk_data.as_ref());
-        let err = chunk.is_err();
-
         assert!(chunk.is_err());
+        assert_eq!(
+            chunk.unwrap_err(),
+            PNGError::InvalidCrc { offset: 0, expected: 2882656334, actual: 2882656333 }
+        );
+    }
+
+    #[test]
+    fn test_truncated_chunk_from_bytes() {
+        let chunk_data: Vec<u8> = [0, 0, 0, 42, 82, 117, 83, 116, 1, 2, 3].to_vec();
+        let chunk = Chunk::try_from(chunk_data.as_ref());
+        assert_eq!(
+            chunk.unwrap_err(),
+            PNGError::Truncated { offset: 0, needed: 12, available: 11 }
+        );
+
+        let chunk_data: Vec<u8> = [0, 0, 0, 42, 82, 117, 83, 116, 1, 2, 3, 4].to_vec();
+        let chunk = Chunk::try_from(chunk_data.as_ref());
+        assert_eq!(
+            chunk.unwrap_err(),
+            PNGError::Truncated { offset: 0, needed: 54, available: 12 }
+        );
+    }
+
+    #[test]
+    fn test_invalid_chunk_type_from_bytes() {
+        let chunk_data: Vec<u8> = [0, 0, 0, 0, 82, 117, 32, 116, 0, 0, 0, 0].to_vec();
+        let chunk = Chunk::try_from(chunk_data.as_ref());
+        assert_eq!(
+            chunk.unwrap_err(),
+            PNGError::InvalidChunkType { offset: 4, bytes: [82, 117, 32, 116] }
+        );
+    }
+
+    #[test]
+    fn test_oversized_chunk_length() {
+        let chunk_data: Vec<u8> = [128, 0, 0, 0, 82, 117, 83, 116, 0, 0, 0, 0].to_vec();
+        let chunk = Chunk::try_from(chunk_data.as_ref());
+        assert_eq!(
+            chunk.unwrap_err(),
+            PNGError::InvalidLength { offset: 0, length: 1 << 31 }
+        );
     }
 
     #[test]
@@ -1,7 +1,10 @@
 use std::str::FromStr;
 use std::{fmt, str};
 
-#[derive(Debug)]
+use crate::error::PNGError;
+
+/// The four ASCII letters naming a chunk, whose letter case encodes its properties
+#[derive(Debug, Clone, Copy)]
 pub struct ChunkType {
     bytes: [u8; 4]
 }
@@ -17,10 +20,11 @@ impl ChunkType {
                 return false;
             }
         }
-        return true;
+        true
     }
 
-    fn is_err(&self) -> bool {
+    /// The opposite of `is_valid`
+    pub fn is_err(&self) -> bool {
         !self.is_valid()
     }
 
@@ -46,10 +50,12 @@ impl ChunkType {
 
     /// Valid bytes are represented by the characters A-Z or a-z
     pub fn is_valid_byte(byte: u8) -> bool {
-        if byte < 65 ||( 90 < byte && byte < 97) || 122 < byte {
-            return false;
-        }
-        true
+        check_valid_ascii(byte)
+    }
+
+    /// Whether this is the chunk type named `chunk_type`, compared byte by byte
+    pub fn matches(&self, chunk_type: &str) -> bool {
+        self.bytes == chunk_type.as_bytes()
     }
 
     pub fn to_string(&self) -> &str {
@@ -75,11 +81,11 @@ impl PartialEq for ChunkType {
 }
 
 impl TryFrom<[u8; 4]> for ChunkType {
-    type Error = &'static str;
+    type Error = PNGError;
 
     fn try_from(value: [u8; 4]) -> Result<Self, Self::Error> {
-        if value.len() != 4 {
-            return Err("Lenght of chunk byte vector needs to be 4");
+        if !value.iter().all(|byte| ChunkType::is_valid_byte(*byte)) {
+            return Err(PNGError::InvalidChunkType { offset: 0, bytes: value });
         }
         let res = ChunkType {bytes: value};
         Ok(res)
@@ -87,23 +93,14 @@ impl TryFrom<[u8; 4]> for ChunkType {
 }
 
 impl FromStr for ChunkType {
-    type Err = &'static str;
+    type Err = PNGError;
 
     fn from_str(value: &str) -> Result<Self, Self::Err> {
-        let bytes = value.as_bytes();
-        if bytes.len() != 4 {
-            return Err("Must contain exactly four ASCII letters!");
-        }
-        for byte in bytes {
-            let ubyte = *byte;
-            if ubyte < 65 ||( 90 < ubyte && ubyte < 97) || 122 < ubyte {
-                return Err("All letters need to be valid ASCII chars!");
-            }
-        }
-        let byte_array: [u8;4] = bytes.try_into().expect("slice with incorrect length!");
-        let res = ChunkType{bytes:byte_array};
-        Ok(res)		
-
+        let byte_array: [u8; 4] = match value.as_bytes().try_into() {
+            Ok(bytes) => bytes,
+            Err(_) => return Err(PNGError::InvalidChunkTypeLength(value.len())),
+        };
+        ChunkType::try_from(byte_array)
     }
 }
 
@@ -114,11 +111,9 @@ fn check_valid_ascii(ubyte: u8) -> bool {
     true
 }
 
+/// The property bits of a chunk type are bit 5 of each byte, which is unset for uppercase letters
 fn is_uppercase(ubyte: u8) -> bool {
-    if ubyte < 90 {
-        return true;
-    }
-    false
+    ubyte & 0x20 == 0
 }
 
 // #![allow(unused_variables)]
@@ -192,6 +187,13 @@ mod tests {
         assert!(!chunk.is_safe_to_copy());
     }
 
+    #[test]
+    pub fn test_chunk_type_z_is_uppercase() {
+        let chunk = ChunkType::from_str("ZuSZ").unwrap();
+        assert!(chunk.is_critical());
+        assert!(!chunk.is_safe_to_copy());
+    }
+
     #[test]
     pub fn test_valid_chunk_is_valid() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -208,6 +210,18 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_invalid_chunk_type_errors() {
+        assert_eq!(
+            ChunkType::from_str("RuSty").unwrap_err(),
+            PNGError::InvalidChunkTypeLength(5)
+        );
+        assert_eq!(
+            ChunkType::try_from([82, 117, 32, 116]).unwrap_err(),
+            PNGError::InvalidChunkType { offset: 0, bytes: [82, 117, 32, 116] }
+        );
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -0,0 +1,487 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use magic_crypt::{new_magic_crypt, MagicCryptTrait};
+use sha2::Sha256;
+use std::fmt;
+use std::str::FromStr;
+use x25519_dalek::{PublicKey, SharedSecret, StaticSecret};
+
+/// Marks the start of every payload written by `encrypt` and `encrypt_to_recipients`.
+///
+/// Legacy magic-crypt payloads are base64 text and can never start with it.
+pub const ENVELOPE_MAGIC: [u8; 4] = *b"\0pme";
+
+/// Version byte following the magic in payloads written by `encrypt`
+pub const ENVELOPE_VERSION: u8 = 1;
+
+/// Version byte following the magic in payloads written by `encrypt_to_recipients`
+pub const RECIPIENT_ENVELOPE_VERSION: u8 = 2;
+
+/// Most recipients a single payload can be encrypted to, the count is stored in one byte
+pub const MAX_RECIPIENTS: usize = u8::MAX as usize;
+
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+pub const KEY_LENGTH: usize = 32;
+// [MAGIC][VERSION][M_COST][T_COST][P_COST][--SALT--][NONCE]
+const HEADER_LENGTH: usize = 4 + 1 + 4 + 4 + 4 + SALT_LENGTH + NONCE_LENGTH;
+// [MAGIC][VERSION][COUNT]
+const RECIPIENT_PREFIX_LENGTH: usize = 4 + 1 + 1;
+const TAG_LENGTH: usize = 16;
+// [EPHEMERAL PUBLIC KEY][WRAPPED CONTENT KEY + TAG]
+const STANZA_LENGTH: usize = KEY_LENGTH + KEY_LENGTH + TAG_LENGTH;
+/// Domain separation for the key wrapping keys
+const WRAP_INFO: &[u8] = b"pngme x25519 v1";
+
+const PUBLIC_KEY_PREFIX: &str = "pngme-x25519-pub-";
+const SECRET_KEY_PREFIX: &str = "pngme-x25519-sec-";
+
+/// Argon2id cost parameters, stored in the envelope so they can be raised later
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    /// Memory in KiB
+    pub m_cost: u32,
+    /// Number of passes
+    pub t_cost: u32,
+    /// Degree of parallelism
+    pub p_cost: u32,
+}
+
+impl KdfParams {
+    /// Upper bounds for parameters read from a file. The key is derived before the tag can be
+    /// checked, so a crafted envelope can make decryption use up to 64 MiB and 64 passes.
+    const MAX_M_COST: u32 = 64 * 1024;
+    const MAX_T_COST: u32 = 64;
+    const MAX_P_COST: u32 = 16;
+
+    fn check_bounds(&self) -> Result<(), CryptoError> {
+        if self.m_cost > KdfParams::MAX_M_COST || self.t_cost > KdfParams::MAX_T_COST || self.p_cost > KdfParams::MAX_P_COST {
+            return Err(CryptoError::InvalidParams(format!("{:?} exceeds the supported limits", self)));
+        }
+        Ok(())
+    }
+}
+
+impl Default for KdfParams {
+    /// The OWASP recommendation for Argon2id: 19 MiB, two passes, one lane
+    fn default() -> Self {
+        KdfParams { m_cost: 19 * 1024, t_cost: 2, p_cost: 1 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CryptoError {
+    /// The payload is shorter than the envelope header
+    Truncated,
+    /// The envelope was written by a newer version of pngme
+    UnsupportedVersion(u8),
+    /// The stored key derivation parameters are rejected by Argon2
+    InvalidParams(String),
+    /// Wrong passphrase, or the payload was modified
+    Authentication,
+    /// A legacy magic-crypt payload could not be decrypted
+    Legacy,
+    /// A public or private key could not be parsed
+    InvalidKey(String),
+    /// The payload was encrypted to recipients but a passphrase was supplied
+    ExpectedIdentity,
+    /// The payload was encrypted with a passphrase but an identity was supplied
+    ExpectedPassphrase,
+    /// None of the recipients the payload was encrypted to matches the identity
+    NoMatchingRecipient,
+}
+
+impl std::error::Error for CryptoError {}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CryptoError::Truncated => write!(f, "Encrypted payload is truncated"),
+            CryptoError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported encryption envelope version {}", version)
+            }
+            CryptoError::InvalidParams(reason) => write!(f, "Invalid key derivation parameters: {}", reason),
+            CryptoError::Authentication => {
+                write!(f, "Wrong passphrase or the message has been tampered with")
+            }
+            CryptoError::Legacy => write!(f, "Failed to decrypt legacy message"),
+            CryptoError::InvalidKey(reason) => write!(f, "Invalid key: {}", reason),
+            CryptoError::ExpectedIdentity => {
+                write!(f, "The message is encrypted to recipients, supply an identity instead of a passphrase")
+            }
+            CryptoError::ExpectedPassphrase => {
+                write!(f, "The message is encrypted with a passphrase, supply it instead of an identity")
+            }
+            CryptoError::NoMatchingRecipient => write!(f, "The message is not encrypted to this identity"),
+        }
+    }
+}
+
+/// Encrypts `plaintext` with a key derived from `passphrase` using the default cost parameters
+pub fn encrypt(passphrase: &str, plaintext: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    encrypt_with_params(passphrase, plaintext, KdfParams::default())
+}
+
+pub fn encrypt_with_params(passphrase: &str, plaintext: &[u8], params: KdfParams) -> Result<Vec<u8>, CryptoError> {
+    let mut salt = [0u8; SALT_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let mut envelope = Vec::with_capacity(HEADER_LENGTH + plaintext.len() + 16);
+    envelope.extend_from_slice(&ENVELOPE_MAGIC);
+    envelope.push(ENVELOPE_VERSION);
+    envelope.extend_from_slice(&params.m_cost.to_be_bytes());
+    envelope.extend_from_slice(&params.t_cost.to_be_bytes());
+    envelope.extend_from_slice(&params.p_cost.to_be_bytes());
+    envelope.extend_from_slice(&salt);
+    envelope.extend_from_slice(&nonce);
+
+    let key = derive_key(passphrase, &salt, params)?;
+    // The header is authenticated too, so the parameters and salt cannot be swapped out
+    let ciphertext = ChaCha20Poly1305::new(&key)
+        .encrypt(&nonce, Payload { msg: plaintext, aad: &envelope })
+        .map_err(|_| CryptoError::Authentication)?;
+    envelope.extend_from_slice(&ciphertext);
+    Ok(envelope)
+}
+
+/// The version byte of a payload written by `encrypt` or `encrypt_to_recipients`, or `None`
+/// if `payload` does not start with `ENVELOPE_MAGIC` and a version
+pub fn envelope_version(payload: &[u8]) -> Option<u8> {
+    payload.strip_prefix(&ENVELOPE_MAGIC[..])?.first().copied()
+}
+
+/// Decrypts a payload written by `encrypt`, or by the magic-crypt based scheme of earlier versions
+pub fn decrypt(passphrase: &str, payload: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    match envelope_version(payload) {
+        Some(ENVELOPE_VERSION) => decrypt_envelope(passphrase, payload),
+        Some(RECIPIENT_ENVELOPE_VERSION) => Err(CryptoError::ExpectedIdentity),
+        Some(version) => Err(CryptoError::UnsupportedVersion(version)),
+        None if payload.starts_with(&ENVELOPE_MAGIC) => Err(CryptoError::Truncated),
+        None => decrypt_legacy(passphrase, payload),
+    }
+}
+
+fn decrypt_envelope(passphrase: &str, payload: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    if payload.len() < HEADER_LENGTH {
+        return Err(CryptoError::Truncated);
+    }
+    let (header, ciphertext) = payload.split_at(HEADER_LENGTH);
+    let read_u32 = |at: usize| u32::from_be_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]]);
+    let params = KdfParams { m_cost: read_u32(5), t_cost: read_u32(9), p_cost: read_u32(13) };
+    let salt = &header[17..17 + SALT_LENGTH];
+    let nonce = Nonce::from_slice(&header[17 + SALT_LENGTH..]);
+    params.check_bounds()?;
+
+    let key = derive_key(passphrase, salt, params)?;
+    ChaCha20Poly1305::new(&key)
+        .decrypt(nonce, Payload { msg: ciphertext, aad: header })
+        .map_err(|_| CryptoError::Authentication)
+}
+
+fn decrypt_legacy(passphrase: &str, payload: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let b64_string = std::str::from_utf8(payload).map_err(|_| CryptoError::Legacy)?;
+    let crypt = new_magic_crypt!(passphrase, 256);
+    crypt.decrypt_base64_to_bytes(b64_string).map_err(|_| CryptoError::Legacy)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], params: KdfParams) -> Result<Key, CryptoError> {
+    let params = Params::new(params.m_cost, params.t_cost, params.p_cost, Some(KEY_LENGTH))
+        .map_err(|e| CryptoError::InvalidParams(e.to_string()))?;
+    let mut key = Key::default();
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| CryptoError::InvalidParams(e.to_string()))?;
+    Ok(key)
+}
+
+/// An X25519 public key that messages can be encrypted to
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Recipient(PublicKey);
+
+impl fmt::Display for Recipient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", encode_key(self.0.as_bytes(), PUBLIC_KEY_PREFIX))
+    }
+}
+
+impl fmt::Debug for Recipient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Recipient({})", self)
+    }
+}
+
+impl FromStr for Recipient {
+    type Err = CryptoError;
+
+    fn from_str(s: &str) -> Result<Self, CryptoError> {
+        Ok(Recipient(PublicKey::from(decode_key(s.trim(), PUBLIC_KEY_PREFIX)?)))
+    }
+}
+
+/// An X25519 private key that decrypts messages sent to its `Recipient`
+///
+/// Neither `Display` nor `Debug` reveal the key, use `to_secret_string` to store it.
+#[derive(Clone)]
+pub struct Identity(StaticSecret);
+
+impl Identity {
+    pub fn generate() -> Identity {
+        let mut bytes = [0u8; KEY_LENGTH];
+        OsRng.fill_bytes(&mut bytes);
+        Identity(StaticSecret::from(bytes))
+    }
+
+    pub fn recipient(&self) -> Recipient {
+        Recipient(PublicKey::from(&self.0))
+    }
+
+    pub fn to_secret_string(&self) -> String {
+        encode_key(self.0.as_bytes(), SECRET_KEY_PREFIX)
+    }
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Identity({})", self.recipient())
+    }
+}
+
+impl FromStr for Identity {
+    type Err = CryptoError;
+
+    /// Parses the contents of an identity file, skipping blank lines and `#` comments
+    fn from_str(s: &str) -> Result<Self, CryptoError> {
+        Ok(Identity(StaticSecret::from(decode_key(key_line(s)?, SECRET_KEY_PREFIX)?)))
+    }
+}
+
+pub fn encode_key(key: &[u8; KEY_LENGTH], prefix: &str) -> String {
+    format!("{}{}", prefix, URL_SAFE_NO_PAD.encode(key))
+}
+
+/// Finds the key in the contents of a key file, skipping blank lines and `#` comments
+pub fn key_line(s: &str) -> Result<&str, CryptoError> {
+    s.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .ok_or_else(|| CryptoError::InvalidKey("no key found".to_string()))
+}
+
+/// Decodes a 32 byte key written as `prefix` followed by unpadded URL-safe base64
+pub fn decode_key(s: &str, prefix: &str) -> Result<[u8; KEY_LENGTH], CryptoError> {
+    let encoded = s
+        .strip_prefix(prefix)
+        .ok_or_else(|| CryptoError::InvalidKey(format!("expected a key starting with {}", prefix)))?;
+    let bytes = URL_SAFE_NO_PAD
+        .decode(encoded)
+        .map_err(|_| CryptoError::InvalidKey("key is not valid base64".to_string()))?;
+    bytes
+        .try_into()
+        .map_err(|_| CryptoError::InvalidKey(format!("key must be {} bytes", KEY_LENGTH)))
+}
+
+/// Encrypts `plaintext` under a random content key and wraps that key for every recipient
+pub fn encrypt_to_recipients(recipients: &[Recipient], plaintext: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    if recipients.is_empty() || recipients.len() > MAX_RECIPIENTS {
+        return Err(CryptoError::InvalidKey(format!("between 1 and {} recipients are supported", MAX_RECIPIENTS)));
+    }
+    let content_key = ChaCha20Poly1305::generate_key(&mut OsRng);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    // [MAGIC][VERSION][COUNT][STANZA]..[STANZA][NONCE]
+    let mut envelope = Vec::with_capacity(
+        RECIPIENT_PREFIX_LENGTH + recipients.len() * STANZA_LENGTH + NONCE_LENGTH + plaintext.len() + TAG_LENGTH,
+    );
+    envelope.extend_from_slice(&ENVELOPE_MAGIC);
+    envelope.push(RECIPIENT_ENVELOPE_VERSION);
+    envelope.push(recipients.len() as u8);
+    for recipient in recipients {
+        let ephemeral = Identity::generate();
+        let ephemeral_public = ephemeral.recipient().0;
+        let wrap_key = wrapping_key(ephemeral.0.diffie_hellman(&recipient.0), &ephemeral_public, &recipient.0)?;
+        // Each wrapping key is used exactly once, so a fixed nonce is safe
+        let wrapped = ChaCha20Poly1305::new(&wrap_key)
+            .encrypt(&Nonce::default(), content_key.as_slice())
+            .map_err(|_| CryptoError::Authentication)?;
+        envelope.extend_from_slice(ephemeral_public.as_bytes());
+        envelope.extend_from_slice(&wrapped);
+    }
+    envelope.extend_from_slice(&nonce);
+
+    // As with passphrases, the header is authenticated so stanzas cannot be added or dropped
+    let ciphertext = ChaCha20Poly1305::new(&content_key)
+        .encrypt(&nonce, Payload { msg: plaintext, aad: &envelope })
+        .map_err(|_| CryptoError::Authentication)?;
+    envelope.extend_from_slice(&ciphertext);
+    Ok(envelope)
+}
+
+/// Decrypts a payload written by `encrypt_to_recipients` if `identity` is one of its recipients
+pub fn decrypt_with_identity(identity: &Identity, payload: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    match envelope_version(payload) {
+        Some(RECIPIENT_ENVELOPE_VERSION) => {}
+        Some(ENVELOPE_VERSION) => return Err(CryptoError::ExpectedPassphrase),
+        Some(version) => return Err(CryptoError::UnsupportedVersion(version)),
+        None if payload.starts_with(&ENVELOPE_MAGIC) => return Err(CryptoError::Truncated),
+        // Anything else can only be a legacy magic-crypt payload
+        None => return Err(CryptoError::ExpectedPassphrase),
+    }
+    let count = *payload.get(RECIPIENT_PREFIX_LENGTH - 1).ok_or(CryptoError::Truncated)? as usize;
+    let header_length = RECIPIENT_PREFIX_LENGTH + count * STANZA_LENGTH + NONCE_LENGTH;
+    if payload.len() < header_length {
+        return Err(CryptoError::Truncated);
+    }
+    let (header, ciphertext) = payload.split_at(header_length);
+    let nonce = Nonce::from_slice(&header[header_length - NONCE_LENGTH..]);
+    let own_public = identity.recipient().0;
+
+    for stanza in header[RECIPIENT_PREFIX_LENGTH..header_length - NONCE_LENGTH].chunks_exact(STANZA_LENGTH) {
+        let (ephemeral_public, wrapped) = stanza.split_at(KEY_LENGTH);
+        let ephemeral_public = PublicKey::from(<[u8; KEY_LENGTH]>::try_from(ephemeral_public).unwrap());
+        let shared = identity.0.diffie_hellman(&ephemeral_public);
+        let wrap_key = match wrapping_key(shared, &ephemeral_public, &own_public) {
+            Ok(key) => key,
+            Err(_) => continue,
+        };
+        if let Ok(content_key) = ChaCha20Poly1305::new(&wrap_key).decrypt(&Nonce::default(), wrapped) {
+            return ChaCha20Poly1305::new(Key::from_slice(&content_key))
+                .decrypt(nonce, Payload { msg: ciphertext, aad: header })
+                .map_err(|_| CryptoError::Authentication);
+        }
+    }
+    Err(CryptoError::NoMatchingRecipient)
+}
+
+/// Derives the key that wraps the content key for one recipient
+///
+/// Both public keys go into the salt, binding the wrapped key to this exact exchange.
+fn wrapping_key(shared: SharedSecret, ephemeral: &PublicKey, recipient: &PublicKey) -> Result<Key, CryptoError> {
+    if !shared.was_contributory() {
+        return Err(CryptoError::InvalidKey("low order public key".to_string()));
+    }
+    let mut salt = [0u8; 2 * KEY_LENGTH];
+    salt[..KEY_LENGTH].copy_from_slice(ephemeral.as_bytes());
+    salt[KEY_LENGTH..].copy_from_slice(recipient.as_bytes());
+    let mut key = Key::default();
+    Hkdf::<Sha256>::new(Some(&salt), shared.as_bytes())
+        .expand(WRAP_INFO, &mut key)
+        .map_err(|e| CryptoError::InvalidKey(e.to_string()))?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Keeps the tests fast, the envelope records whatever parameters were used
+    const TEST_PARAMS: KdfParams = KdfParams { m_cost: 64, t_cost: 1, p_cost: 1 };
+
+    #[test]
+    fn test_round_trip() {
+        let payload = encrypt_with_params("hunter2", b"secret message", TEST_PARAMS).unwrap();
+        assert_eq!(envelope_version(&payload), Some(ENVELOPE_VERSION));
+        assert_eq!(decrypt("hunter2", &payload).unwrap(), b"secret message");
+    }
+
+    #[test]
+    fn test_random_salt_and_nonce() {
+        let first = encrypt_with_params("hunter2", b"secret message", TEST_PARAMS).unwrap();
+        let second = encrypt_with_params("hunter2", b"secret message", TEST_PARAMS).unwrap();
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_wrong_passphrase() {
+        let payload = encrypt_with_params("hunter2", b"secret message", TEST_PARAMS).unwrap();
+        assert_eq!(decrypt("hunter3", &payload).unwrap_err(), CryptoError::Authentication);
+    }
+
+    #[test]
+    fn test_tampering_is_detected() {
+        let payload = encrypt_with_params("hunter2", b"secret message", TEST_PARAMS).unwrap();
+        // Flip a bit in the salt, the ciphertext and the tag in turn
+        for index in [20, HEADER_LENGTH, payload.len() - 1] {
+            let mut tampered = payload.clone();
+            tampered[index] ^= 1;
+            assert_eq!(decrypt("hunter2", &tampered).unwrap_err(), CryptoError::Authentication);
+        }
+    }
+
+    #[test]
+    fn test_truncated_and_unknown_versions() {
+        let payload = encrypt_with_params("hunter2", b"secret message", TEST_PARAMS).unwrap();
+        assert_eq!(decrypt("hunter2", &payload[..10]).unwrap_err(), CryptoError::Truncated);
+        assert_eq!(decrypt("hunter2", b"\0pme\x03\0\0").unwrap_err(), CryptoError::UnsupportedVersion(3));
+        assert_eq!(decrypt("hunter2", &ENVELOPE_MAGIC).unwrap_err(), CryptoError::Truncated);
+    }
+
+    #[test]
+    fn test_excessive_params_are_rejected() {
+        let mut payload = encrypt_with_params("hunter2", b"secret message", TEST_PARAMS).unwrap();
+        // One KiB over the memory cap
+        payload[5..9].copy_from_slice(&(64 * 1024 + 1u32).to_be_bytes());
+        assert!(matches!(decrypt("hunter2", &payload).unwrap_err(), CryptoError::InvalidParams(_)));
+    }
+
+    #[test]
+    fn test_recipient_round_trip() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let payload = encrypt_to_recipients(&[alice.recipient(), bob.recipient()], b"secret message").unwrap();
+        assert_eq!(envelope_version(&payload), Some(RECIPIENT_ENVELOPE_VERSION));
+        assert_eq!(decrypt_with_identity(&alice, &payload).unwrap(), b"secret message");
+        assert_eq!(decrypt_with_identity(&bob, &payload).unwrap(), b"secret message");
+
+        let eve = Identity::generate();
+        assert_eq!(decrypt_with_identity(&eve, &payload).unwrap_err(), CryptoError::NoMatchingRecipient);
+    }
+
+    #[test]
+    fn test_recipient_tampering_is_detected() {
+        let alice = Identity::generate();
+        let payload = encrypt_to_recipients(&[alice.recipient()], b"secret message").unwrap();
+        let nonce_at = RECIPIENT_PREFIX_LENGTH + STANZA_LENGTH;
+        let mut tampered = payload.clone();
+        tampered[nonce_at] ^= 1;
+        assert_eq!(decrypt_with_identity(&alice, &tampered).unwrap_err(), CryptoError::Authentication);
+        assert_eq!(decrypt_with_identity(&alice, &payload[..nonce_at]).unwrap_err(), CryptoError::Truncated);
+    }
+
+    #[test]
+    fn test_key_kind_mismatch() {
+        let alice = Identity::generate();
+        let to_alice = encrypt_to_recipients(&[alice.recipient()], b"secret message").unwrap();
+        assert_eq!(decrypt("hunter2", &to_alice).unwrap_err(), CryptoError::ExpectedIdentity);
+        let with_passphrase = encrypt_with_params("hunter2", b"secret message", TEST_PARAMS).unwrap();
+        assert_eq!(decrypt_with_identity(&alice, &with_passphrase).unwrap_err(), CryptoError::ExpectedPassphrase);
+    }
+
+    #[test]
+    fn test_key_strings() {
+        let identity = Identity::generate();
+        let recipient = identity.recipient();
+        let parsed = Recipient::from_str(&recipient.to_string()).unwrap();
+        assert_eq!(parsed, recipient);
+
+        let file = format!("# public key: {}\n{}\n", recipient, identity.to_secret_string());
+        assert_eq!(Identity::from_str(&file).unwrap().recipient(), recipient);
+        assert!(!format!("{:?}", identity).contains(&identity.to_secret_string()));
+
+        assert!(Recipient::from_str(&identity.to_secret_string()).is_err());
+        assert!(Recipient::from_str("pngme-x25519-pub-AAAA").is_err());
+    }
+
+    #[test]
+    fn test_legacy_payload() {
+        let crypt = new_magic_crypt!("hunter2", 256);
+        let legacy = crypt.encrypt_str_to_base64("old message");
+        assert_eq!(decrypt("hunter2", legacy.as_bytes()).unwrap(), b"old message");
+        assert_eq!(decrypt("hunter3", legacy.as_bytes()).unwrap_err(), CryptoError::Legacy);
+    }
+}
@@ -0,0 +1,107 @@
+use std::{fmt, io};
+
+/// Errors produced while parsing PNG signatures, chunks and chunk types.
+///
+/// Offsets are byte positions in the input that was handed to the parser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PNGError {
+    /// The input ended before a complete structure could be read
+    Truncated { offset: usize, needed: usize, available: usize },
+    /// A chunk declares a data length above the 2^31 - 1 limit of the spec
+    InvalidLength { offset: usize, length: u32 },
+    /// The stored CRC of a chunk does not match the one computed from its contents
+    InvalidCrc { offset: usize, expected: u32, actual: u32 },
+    /// A chunk type contains bytes outside of A-Z and a-z
+    InvalidChunkType { offset: usize, bytes: [u8; 4] },
+    /// A chunk type string does not have exactly four bytes
+    InvalidChunkTypeLength(usize),
+    /// The file does not start with the PNG signature
+    InvalidSignature([u8; 8]),
+    /// The first chunk is not `IHDR`
+    MissingIhdr,
+    /// The `IHDR` chunk violates the spec, with a description of what is wrong
+    InvalidIhdr(String),
+    /// A `tEXt`, `zTXt` or `iTXt` chunk is malformed, with a description of what is wrong
+    InvalidText(String),
+    /// Reading the input failed, as opposed to the input being malformed
+    Io { offset: usize, kind: io::ErrorKind },
+    /// There is no chunk at `index` in a file of `len` chunks
+    InvalidIndex { index: usize, len: usize },
+    /// A chunk of this type is needed but the file has none
+    MissingChunk(String),
+    /// A chunk would end up at `index`, in front of `IHDR`
+    BeforeIhdr { index: usize },
+    /// A chunk would end up at `index`, behind `IEND`
+    AfterIend { index: usize },
+}
+
+impl PNGError {
+    /// Shifts all byte offsets by `base`, used when a chunk was parsed from a sub-slice
+    pub fn offset_by(self, base: usize) -> Self {
+        match self {
+            PNGError::Truncated { offset, needed, available } => {
+                PNGError::Truncated { offset: offset + base, needed, available }
+            }
+            PNGError::InvalidLength { offset, length } => {
+                PNGError::InvalidLength { offset: offset + base, length }
+            }
+            PNGError::InvalidCrc { offset, expected, actual } => {
+                PNGError::InvalidCrc { offset: offset + base, expected, actual }
+            }
+            PNGError::InvalidChunkType { offset, bytes } => {
+                PNGError::InvalidChunkType { offset: offset + base, bytes }
+            }
+            PNGError::Io { offset, kind } => PNGError::Io { offset: offset + base, kind },
+            other => other,
+        }
+    }
+}
+
+impl std::error::Error for PNGError {}
+
+impl fmt::Display for PNGError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PNGError::Truncated { offset, needed, available } => write!(
+                f,
+                "Input truncated at byte {}: needed {} bytes but only {} are left",
+                offset, needed, available
+            ),
+            PNGError::InvalidLength { offset, length } => write!(
+                f,
+                "Chunk at byte {} declares length {} which exceeds 2^31 - 1",
+                offset, length
+            ),
+            PNGError::InvalidCrc { offset, expected, actual } => write!(
+                f,
+                "CRC mismatch in chunk at byte {}: expected {:#010x}, found {:#010x}",
+                offset, expected, actual
+            ),
+            PNGError::InvalidChunkType { offset, bytes } => write!(
+                f,
+                "Invalid chunk type {:?} at byte {}: only A-Z and a-z are allowed",
+                bytes, offset
+            ),
+            PNGError::InvalidChunkTypeLength(len) => write!(
+                f,
+                "Chunk type must contain exactly four ASCII letters, got {} bytes",
+                len
+            ),
+            PNGError::InvalidSignature(found) => {
+                write!(f, "Invalid PNG signature {:?}", found)
+            }
+            PNGError::MissingIhdr => write!(f, "The first chunk is not IHDR"),
+            PNGError::InvalidIhdr(reason) => write!(f, "Invalid IHDR chunk: {}", reason),
+            PNGError::InvalidText(reason) => write!(f, "Invalid text chunk: {}", reason),
+            PNGError::Io { offset, kind } => write!(f, "Could not read input at byte {}: {}", offset, kind),
+            PNGError::InvalidIndex { index, len } => {
+                write!(f, "No chunk at index {}, the file has {} chunks", index, len)
+            }
+            PNGError::MissingChunk(chunk_type) => write!(f, "No {} chunk found", chunk_type),
+            PNGError::BeforeIhdr { index } => {
+                write!(f, "A chunk at index {} would come in front of IHDR", index)
+            }
+            PNGError::AfterIend { index } => write!(f, "A chunk at index {} would come behind IEND", index),
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use crate::chunk::Chunk;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+/// Marks chunk data that is one piece of a larger payload
+pub const FRAGMENT_MAGIC: [u8; 4] = *b"\0pmp";
+
+/// Version of the fragment header written by `split`
+pub const FRAGMENT_VERSION: u8 = 2;
+
+/// Version of fragment headers without a message id, still read
+const V1_FRAGMENT_VERSION: u8 = 1;
+
+pub const ID_LENGTH: usize = 16;
+const HASH_LENGTH: usize = 32;
+// [MAGIC][VERSION][SEQUENCE][TOTAL][MESSAGE ID][PAYLOAD HASH]
+pub const HEADER_LENGTH: usize = 4 + 1 + 4 + 4 + ID_LENGTH + HASH_LENGTH;
+// [MAGIC][VERSION][SEQUENCE][TOTAL][PAYLOAD HASH]
+const V1_HEADER_LENGTH: usize = 4 + 1 + 4 + 4 + HASH_LENGTH;
+
+/// Largest piece of payload that fits in one fragment chunk
+pub const MAX_FRAGMENT_SIZE: usize = Chunk::MAX_LENGTH as usize - HEADER_LENGTH;
+
+/// How many more fragments than were found a header may claim and still have the missing ones
+/// listed, larger claims are rejected before anything is allocated for them
+const MAX_LISTED_MISSING: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FragmentError {
+    /// The chunk data ends before its fragment header does
+    Truncated,
+    /// The fragment was written by a newer version of pngme
+    UnsupportedVersion(u8),
+    /// The fragment size is zero or does not fit in a chunk
+    InvalidSize(usize),
+    /// A header contradicts the others or itself
+    Inconsistent(String),
+    /// Fragments with these sequence numbers could not be found
+    Missing(Vec<u32>),
+    /// The reassembled payload does not match the stored hash
+    HashMismatch,
+}
+
+impl std::error::Error for FragmentError {}
+
+impl fmt::Display for FragmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FragmentError::Truncated => write!(f, "Fragment header is truncated"),
+            FragmentError::UnsupportedVersion(version) => write!(f, "Unsupported fragment version {}", version),
+            FragmentError::InvalidSize(size) => {
+                write!(f, "Fragment size must be between 1 and {} bytes, got {}", MAX_FRAGMENT_SIZE, size)
+            }
+            FragmentError::Inconsistent(reason) => write!(f, "Inconsistent fragments: {}", reason),
+            FragmentError::Missing(sequences) => {
+                let sequences: Vec<String> = sequences.iter().map(|sequence| sequence.to_string()).collect();
+                write!(f, "Missing fragments {}", sequences.join(", "))
+            }
+            FragmentError::HashMismatch => write!(f, "Reassembled payload does not match its hash, a fragment is corrupted"),
+        }
+    }
+}
+
+/// The header of a single fragment and the piece of payload it carries
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fragment<'a> {
+    /// Position of this piece, counting from zero
+    pub sequence: u32,
+    pub total: u32,
+    /// Random and shared by all fragments of one message, so two copies of the same payload stay apart
+    ///
+    /// Version 1 fragments have none, the start of their hash stands in for it.
+    pub id: [u8; ID_LENGTH],
+    /// SHA-256 of the whole payload, to check the reassembled payload against
+    pub hash: [u8; HASH_LENGTH],
+    pub piece: &'a [u8],
+}
+
+impl<'a> TryFrom<&'a [u8]> for Fragment<'a> {
+    type Error = FragmentError;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, FragmentError> {
+        if value.len() < 5 || !is_fragment(value) {
+            return Err(FragmentError::Truncated);
+        }
+        let header_length = match value[4] {
+            FRAGMENT_VERSION => HEADER_LENGTH,
+            V1_FRAGMENT_VERSION => V1_HEADER_LENGTH,
+            version => return Err(FragmentError::UnsupportedVersion(version)),
+        };
+        if value.len() < header_length {
+            return Err(FragmentError::Truncated);
+        }
+        let sequence = u32::from_be_bytes(value[5..9].try_into().unwrap());
+        let total = u32::from_be_bytes(value[9..13].try_into().unwrap());
+        if sequence >= total {
+            return Err(FragmentError::Inconsistent(format!("fragment {} of {}", sequence, total)));
+        }
+        let hash: [u8; HASH_LENGTH] = value[header_length - HASH_LENGTH..header_length].try_into().unwrap();
+        let id = match value[4] {
+            FRAGMENT_VERSION => value[13..13 + ID_LENGTH].try_into().unwrap(),
+            _ => hash[..ID_LENGTH].try_into().unwrap(),
+        };
+        Ok(Fragment {
+            sequence,
+            total,
+            id,
+            hash,
+            piece: &value[header_length..],
+        })
+    }
+}
+
+/// Whether chunk data starts with a fragment header
+pub fn is_fragment(data: &[u8]) -> bool {
+    data.starts_with(&FRAGMENT_MAGIC)
+}
+
+/// Splits `payload` into pieces of at most `size` bytes, each prefixed with a fragment header
+///
+/// Every call picks a new message id, so splitting the same payload twice gives two separate messages.
+pub fn split(payload: &[u8], size: usize) -> Result<Vec<Vec<u8>>, FragmentError> {
+    if size == 0 || size > MAX_FRAGMENT_SIZE {
+        return Err(FragmentError::InvalidSize(size));
+    }
+    let mut id = [0u8; ID_LENGTH];
+    OsRng.fill_bytes(&mut id);
+    let hash: [u8; HASH_LENGTH] = Sha256::digest(payload).into();
+    let pieces: Vec<&[u8]> = match payload.is_empty() {
+        true => vec![payload],
+        false => payload.chunks(size).collect(),
+    };
+    let total = u32::try_from(pieces.len()).map_err(|_| FragmentError::InvalidSize(size))?;
+    Ok(pieces
+        .iter()
+        .enumerate()
+        .map(|(sequence, piece)| {
+            let mut data = Vec::with_capacity(HEADER_LENGTH + piece.len());
+            data.extend_from_slice(&FRAGMENT_MAGIC);
+            data.push(FRAGMENT_VERSION);
+            data.extend_from_slice(&(sequence as u32).to_be_bytes());
+            data.extend_from_slice(&total.to_be_bytes());
+            data.extend_from_slice(&id);
+            data.extend_from_slice(&hash);
+            data.extend_from_slice(piece);
+            data
+        })
+        .collect())
+}
+
+/// Puts the fragments of one payload back together in sequence order
+///
+/// The fragments may come in any order. Fragments of other messages, recognised by a different
+/// id than the first fragment, are ignored so several fragmented messages can share a chunk type.
+pub fn reassemble<'a>(fragments: impl IntoIterator<Item = &'a [u8]>) -> Result<Vec<u8>, FragmentError> {
+    let mut fragments = fragments.into_iter().map(Fragment::try_from);
+    let first = fragments.next().ok_or(FragmentError::Missing(vec![0]))??;
+    let total = first.total;
+    let mut found = vec![first];
+    for fragment in fragments {
+        let fragment = fragment?;
+        if fragment.id != found[0].id {
+            continue;
+        }
+        if fragment.hash != found[0].hash {
+            return Err(FragmentError::Inconsistent("fragments of one message carry different hashes".to_string()));
+        }
+        if fragment.total != total {
+            return Err(FragmentError::Inconsistent(format!("expected {} fragments, found {}", total, fragment.total)));
+        }
+        found.push(fragment);
+    }
+    // The total comes from the file, so it is checked against what is there before allocating for it
+    if total as usize > found.len() + MAX_LISTED_MISSING {
+        return Err(FragmentError::Inconsistent(format!("expected {} fragments, only {} are present", total, found.len())));
+    }
+
+    let mut pieces: Vec<Option<&[u8]>> = vec![None; total as usize];
+    for fragment in &found {
+        if pieces[fragment.sequence as usize].replace(fragment.piece).is_some() {
+            return Err(FragmentError::Inconsistent(format!("fragment {} appears twice", fragment.sequence)));
+        }
+    }
+
+    let missing: Vec<u32> = (0..total).filter(|&sequence| pieces[sequence as usize].is_none()).collect();
+    if !missing.is_empty() {
+        return Err(FragmentError::Missing(missing));
+    }
+    let payload: Vec<u8> = pieces.into_iter().flatten().flatten().copied().collect();
+    if Sha256::digest(&payload)[..] != found[0].hash {
+        return Err(FragmentError::HashMismatch);
+    }
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload() -> Vec<u8> {
+        (0..1000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_split_and_reassemble() {
+        let fragments = split(&payload(), 300).unwrap();
+        assert_eq!(fragments.len(), 4);
+        assert!(fragments.iter().all(|fragment| is_fragment(fragment)));
+        assert_eq!(reassemble(fragments.iter().map(|f| &f[..])).unwrap(), payload());
+        assert_eq!(reassemble(fragments.iter().rev().map(|f| &f[..])).unwrap(), payload());
+    }
+
+    #[test]
+    fn test_empty_payload() {
+        let fragments = split(&[], 10).unwrap();
+        assert_eq!(fragments.len(), 1);
+        assert!(reassemble(fragments.iter().map(|f| &f[..])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_missing_fragments() {
+        let fragments = split(&payload(), 100).unwrap();
+        let kept = fragments.iter().enumerate().filter(|(i, _)| *i != 3 && *i != 7).map(|(_, f)| &f[..]);
+        assert_eq!(reassemble(kept).unwrap_err(), FragmentError::Missing(vec![3, 7]));
+    }
+
+    #[test]
+    fn test_corrupted_fragment() {
+        let mut fragments = split(&payload(), 300).unwrap();
+        fragments[2][HEADER_LENGTH] ^= 1;
+        assert_eq!(reassemble(fragments.iter().map(|f| &f[..])).unwrap_err(), FragmentError::HashMismatch);
+    }
+
+    #[test]
+    fn test_other_payloads_are_ignored() {
+        let first = split(&payload(), 300).unwrap();
+        let second = split(b"another message", 5).unwrap();
+        let mixed = first.iter().chain(second.iter()).map(|f| &f[..]);
+        assert_eq!(reassemble(mixed).unwrap(), payload());
+    }
+
+    #[test]
+    fn test_same_payload_twice_stays_apart() {
+        let first = split(b"same message", 4).unwrap();
+        let second = split(b"same message", 4).unwrap();
+        assert_ne!(Fragment::try_from(&first[0][..]).unwrap().id, Fragment::try_from(&second[0][..]).unwrap().id);
+        let mixed = first.iter().chain(second.iter()).map(|f| &f[..]);
+        assert_eq!(reassemble(mixed).unwrap(), b"same message");
+        let mixed = second.iter().chain(first.iter()).map(|f| &f[..]);
+        assert_eq!(reassemble(mixed).unwrap(), b"same message");
+    }
+
+    #[test]
+    fn test_version_1_fragments_are_read() {
+        // A version 1 header is the version 2 header without the id
+        let v1: Vec<Vec<u8>> = split(&payload(), 300)
+            .unwrap()
+            .into_iter()
+            .map(|mut fragment| {
+                fragment[4] = V1_FRAGMENT_VERSION;
+                fragment.drain(13..13 + ID_LENGTH);
+                fragment
+            })
+            .collect();
+        let fragment = Fragment::try_from(&v1[0][..]).unwrap();
+        assert_eq!(fragment.id[..], fragment.hash[..ID_LENGTH]);
+        assert_eq!(fragment.piece.len(), 300);
+        assert_eq!(reassemble(v1.iter().map(|f| &f[..])).unwrap(), payload());
+    }
+
+    #[test]
+    fn test_forged_total_is_rejected_before_allocating() {
+        let mut forged = split(b"tiny", 10).unwrap().remove(0);
+        forged[9..13].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(
+            reassemble([&forged[..]]).unwrap_err(),
+            FragmentError::Inconsistent(format!("expected {} fragments, only 1 are present", u32::MAX))
+        );
+    }
+
+    #[test]
+    fn test_duplicate_and_invalid() {
+        let fragments = split(&payload(), 300).unwrap();
+        let doubled = fragments.iter().chain(fragments.iter().take(1)).map(|f| &f[..]);
+        assert!(matches!(reassemble(doubled).unwrap_err(), FragmentError::Inconsistent(_)));
+        assert_eq!(split(&payload(), 0).unwrap_err(), FragmentError::InvalidSize(0));
+        assert_eq!(Fragment::try_from(&fragments[0][..10]).unwrap_err(), FragmentError::Truncated);
+        assert_eq!(Fragment::try_from(&fragments[0][..HEADER_LENGTH - 1]).unwrap_err(), FragmentError::Truncated);
+        let mut future = fragments[0].clone();
+        future[4] = FRAGMENT_VERSION + 1;
+        assert_eq!(Fragment::try_from(&future[..]).unwrap_err(), FragmentError::UnsupportedVersion(FRAGMENT_VERSION + 1));
+    }
+}
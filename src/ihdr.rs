@@ -0,0 +1,260 @@
+use crate::chunk::Chunk;
+use crate::error::PNGError;
+use crate::view::ChunkRef;
+use serde::Serialize;
+use std::fmt;
+
+/// How pixel samples are to be interpreted, as stored in byte 9 of `IHDR`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorType {
+    Grayscale,
+    Rgb,
+    Indexed,
+    GrayscaleAlpha,
+    Rgba,
+}
+
+impl ColorType {
+    pub fn from_byte(byte: u8) -> Option<ColorType> {
+        match byte {
+            0 => Some(ColorType::Grayscale),
+            2 => Some(ColorType::Rgb),
+            3 => Some(ColorType::Indexed),
+            4 => Some(ColorType::GrayscaleAlpha),
+            6 => Some(ColorType::Rgba),
+            _ => None,
+        }
+    }
+
+    pub fn byte(&self) -> u8 {
+        match self {
+            ColorType::Grayscale => 0,
+            ColorType::Rgb => 2,
+            ColorType::Indexed => 3,
+            ColorType::GrayscaleAlpha => 4,
+            ColorType::Rgba => 6,
+        }
+    }
+
+    /// Bit depths the PNG spec allows in combination with this color type
+    pub fn allowed_bit_depths(&self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Rgb | ColorType::GrayscaleAlpha | ColorType::Rgba => &[8, 16],
+        }
+    }
+}
+
+impl fmt::Display for ColorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColorType::Grayscale => "grayscale",
+            ColorType::Rgb => "RGB",
+            ColorType::Indexed => "indexed",
+            ColorType::GrayscaleAlpha => "grayscale with alpha",
+            ColorType::Rgba => "RGBA",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Interlace method, as stored in byte 12 of `IHDR`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interlace {
+    None,
+    Adam7,
+}
+
+/// The decoded contents of the `IHDR` chunk that every PNG starts with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Ihdr {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    pub compression_method: u8,
+    pub filter_method: u8,
+    pub interlace: Interlace,
+}
+
+impl Ihdr {
+    pub const LENGTH: usize = 13;
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Ihdr::LENGTH);
+        bytes.extend_from_slice(&self.width.to_be_bytes());
+        bytes.extend_from_slice(&self.height.to_be_bytes());
+        bytes.push(self.bit_depth);
+        bytes.push(self.color_type.byte());
+        bytes.push(self.compression_method);
+        bytes.push(self.filter_method);
+        bytes.push(match self.interlace {
+            Interlace::None => 0,
+            Interlace::Adam7 => 1,
+        });
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for Ihdr {
+    // [WIDTH][HEIGHT][DEPTH][COLOR][COMPRESSION][FILTER][INTERLACE]
+    type Error = PNGError;
+
+    fn try_from(value: &[u8]) -> Result<Self, PNGError> {
+        if value.len() != Ihdr::LENGTH {
+            return Err(invalid(format!("data must be {} bytes, found {}", Ihdr::LENGTH, value.len())));
+        }
+        let width = u32::from_be_bytes([value[0], value[1], value[2], value[3]]);
+        let height = u32::from_be_bytes([value[4], value[5], value[6], value[7]]);
+        if width == 0 || width > Chunk::MAX_LENGTH {
+            return Err(invalid(format!("width {} is out of range", width)));
+        }
+        if height == 0 || height > Chunk::MAX_LENGTH {
+            return Err(invalid(format!("height {} is out of range", height)));
+        }
+        let bit_depth = value[8];
+        let color_type = match ColorType::from_byte(value[9]) {
+            Some(color_type) => color_type,
+            None => return Err(invalid(format!("unknown color type {}", value[9]))),
+        };
+        if !color_type.allowed_bit_depths().contains(&bit_depth) {
+            return Err(invalid(format!("bit depth {} is not allowed for {} images", bit_depth, color_type)));
+        }
+        if value[10] != 0 {
+            return Err(invalid(format!("unknown compression method {}", value[10])));
+        }
+        if value[11] != 0 {
+            return Err(invalid(format!("unknown filter method {}", value[11])));
+        }
+        let interlace = match value[12] {
+            0 => Interlace::None,
+            1 => Interlace::Adam7,
+            other => return Err(invalid(format!("unknown interlace method {}", other))),
+        };
+        Ok(Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            compression_method: value[10],
+            filter_method: value[11],
+            interlace,
+        })
+    }
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = PNGError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, PNGError> {
+        if chunk.chunk_type().to_string() != "IHDR" {
+            return Err(PNGError::MissingIhdr);
+        }
+        Ihdr::try_from(chunk.data())
+    }
+}
+
+impl TryFrom<ChunkRef<'_>> for Ihdr {
+    type Error = PNGError;
+
+    fn try_from(chunk: ChunkRef<'_>) -> Result<Self, PNGError> {
+        if !chunk.is_type("IHDR") {
+            return Err(PNGError::MissingIhdr);
+        }
+        Ihdr::try_from(chunk.data())
+    }
+}
+
+impl fmt::Display for Ihdr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{}, {}-bit {}, {}",
+            self.width,
+            self.height,
+            self.bit_depth,
+            self.color_type,
+            match self.interlace {
+                Interlace::None => "non-interlaced",
+                Interlace::Adam7 => "Adam7 interlaced",
+            }
+        )
+    }
+}
+
+fn invalid(reason: String) -> PNGError {
+    PNGError::InvalidIhdr(reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_ihdr() -> Ihdr {
+        Ihdr {
+            width: 50,
+            height: 40,
+            bit_depth: 8,
+            color_type: ColorType::Rgba,
+            compression_method: 0,
+            filter_method: 0,
+            interlace: Interlace::None,
+        }
+    }
+
+    #[test]
+    fn test_ihdr_round_trip() {
+        let ihdr = testing_ihdr();
+        let parsed = Ihdr::try_from(&ihdr.as_bytes()[..]).unwrap();
+        assert_eq!(parsed, ihdr);
+        assert_eq!(parsed.to_string(), "50x40, 8-bit RGBA, non-interlaced");
+    }
+
+    #[test]
+    fn test_ihdr_wrong_length() {
+        let bytes = testing_ihdr().as_bytes();
+        assert!(Ihdr::try_from(&bytes[..12]).is_err());
+    }
+
+    #[test]
+    fn test_ihdr_zero_width() {
+        let ihdr = Ihdr { width: 0, ..testing_ihdr() };
+        assert!(Ihdr::try_from(&ihdr.as_bytes()[..]).is_err());
+    }
+
+    #[test]
+    fn test_ihdr_bit_depth_combinations() {
+        for (color_type, depth, allowed) in [
+            (0, 1, true),
+            (0, 16, true),
+            (2, 4, false),
+            (3, 8, true),
+            (3, 16, false),
+            (4, 8, true),
+            (6, 2, false),
+            (5, 8, false),
+        ] {
+            let mut bytes = testing_ihdr().as_bytes();
+            bytes[8] = depth;
+            bytes[9] = color_type;
+            assert_eq!(Ihdr::try_from(&bytes[..]).is_ok(), allowed, "{} {}", color_type, depth);
+        }
+    }
+
+    #[test]
+    fn test_ihdr_unknown_methods() {
+        for index in [10, 11] {
+            let mut bytes = testing_ihdr().as_bytes();
+            bytes[index] = 1;
+            assert!(Ihdr::try_from(&bytes[..]).is_err());
+        }
+        let mut bytes = testing_ihdr().as_bytes();
+        bytes[12] = 1;
+        assert_eq!(Ihdr::try_from(&bytes[..]).unwrap().interlace, Interlace::Adam7);
+        bytes[12] = 2;
+        assert!(Ihdr::try_from(&bytes[..]).is_err());
+    }
+}
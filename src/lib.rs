@@ -0,0 +1,74 @@
+//! Reading, editing and writing PNG files, and hiding messages in their chunks
+//!
+//! A file is parsed into a [`Png`], a list of [`Chunk`]s that can be inspected, inserted, replaced
+//! and removed before serializing it again with [`Png::as_bytes`]. Large files can be read and
+//! written chunk by chunk with [`ChunkReader`] and [`ChunkWriter`], or inspected in place through
+//! the borrowed [`PngRef`]. The [`message`] module builds on that to embed text or files,
+//! optionally compressed, encrypted, split and signed, and to extract them again. Nothing in this
+//! crate prints or touches the file system, that is left to the `pngme` command line tool.
+//!
+//! ```
+//! use pngme::message::{self, Decryption, EmbedOptions, Encryption};
+//! use pngme::{Chunk, ChunkType, Payload, Png, PngRef};
+//! use std::str::FromStr;
+//!
+//! let mut png = Png::from_chunks(vec![Chunk::new(ChunkType::from_str("IEND")?, Vec::new())]);
+//! let chunk_type = ChunkType::from_str("ruSt")?;
+//!
+//! let data = message::seal(&Payload::text("hidden in plain sight"), &Encryption::None)?;
+//! let embedded = message::embed(&mut png, &chunk_type, &data, &EmbedOptions::default())?;
+//! assert_eq!(embedded.index, 0);
+//!
+//! let bytes = png.as_bytes();
+//! let png = PngRef::try_from(&bytes[..])?;
+//! let chunks: Vec<&[u8]> = png.chunks_by_type("ruSt").map(|chunk| chunk.data()).collect();
+//! let payload = message::open(&message::extract(&chunks, 0)?, &Decryption::None)?;
+//! assert_eq!(payload.data, b"hidden in plain sight");
+//! # Ok::<(), pngme::Error>(())
+//! ```
+
+/// A single chunk: type, data and CRC
+pub mod chunk;
+/// Four-letter chunk types and their property bits
+pub mod chunk_type;
+/// Passphrase and public key encryption of chunk data
+pub mod crypto;
+/// Errors raised while parsing PNG files and chunks
+pub mod error;
+/// Splitting payloads across several chunks and putting them back together
+pub mod fragment;
+/// The `IHDR` image header
+pub mod ihdr;
+/// Embedding messages into a `Png` and extracting them again
+pub mod message;
+/// Framing of text and files, with optional compression
+pub mod payload;
+/// Whole PNG files as a list of chunks
+pub mod png;
+/// Reading chunks one at a time from any `std::io::Read`
+pub mod reader;
+/// Ed25519 signatures over single chunks
+pub mod sign;
+/// The `tEXt`, `zTXt` and `iTXt` text chunks
+pub mod text;
+/// Checks of chunk order and multiplicity against the PNG spec
+pub mod validate;
+/// Zero-copy views of chunks and files in a borrowed buffer
+pub mod view;
+/// Writing chunks one at a time to any `std::io::Write`
+pub mod writer;
+
+pub use chunk::Chunk;
+pub use chunk_type::ChunkType;
+pub use error::PNGError;
+pub use payload::Payload;
+pub use png::Png;
+pub use reader::ChunkReader;
+pub use view::{ChunkRef, PngRef};
+pub use writer::ChunkWriter;
+
+/// Error returned by operations that can fail in more than one way, such as `Chunk::data_as_string`
+///
+/// It is `Send` and `Sync`, so it can cross threads and convert into other error types such as `anyhow::Error`.
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+pub type Result<T> = std::result::Result<T, Error>;
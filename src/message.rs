@@ -0,0 +1,361 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::crypto::{self, CryptoError, Identity, Recipient};
+use crate::error::PNGError;
+use crate::fragment::{self, Fragment, FragmentError};
+use crate::payload::{Payload, PayloadError};
+use crate::png::{ChunkPosition, Png};
+use crate::sign::{self, SignatureError, SignerKey, SigningIdentity};
+use std::fmt;
+
+#[derive(Debug)]
+pub enum MessageError {
+    /// The payload could not be framed or read back
+    Payload(PayloadError),
+    /// Encryption or decryption failed
+    Crypto(CryptoError),
+    /// The message could not be split or reassembled
+    Fragment(FragmentError),
+    /// The data is encrypted with a passphrase, but none was given
+    Encrypted,
+    /// The data is encrypted to recipients, but no identity was given
+    EncryptedToRecipients,
+    /// There is no chunk at this index
+    IndexOutOfRange(usize),
+    /// A chunk could not be placed in the file
+    Placement(PNGError),
+}
+
+impl std::error::Error for MessageError {}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageError::Payload(e) => write!(f, "{}", e),
+            MessageError::Crypto(e) => write!(f, "{}", e),
+            MessageError::Fragment(e) => write!(f, "{}", e),
+            MessageError::Encrypted => write!(f, "The message is encrypted with a passphrase"),
+            MessageError::EncryptedToRecipients => write!(f, "The message is encrypted to recipients"),
+            MessageError::IndexOutOfRange(index) => write!(f, "No chunk at index {}", index),
+            MessageError::Placement(reason) => write!(f, "Could not place the chunk: {}", reason),
+        }
+    }
+}
+
+impl From<PayloadError> for MessageError {
+    fn from(e: PayloadError) -> Self {
+        MessageError::Payload(e)
+    }
+}
+
+impl From<CryptoError> for MessageError {
+    fn from(e: CryptoError) -> Self {
+        MessageError::Crypto(e)
+    }
+}
+
+impl From<FragmentError> for MessageError {
+    fn from(e: FragmentError) -> Self {
+        MessageError::Fragment(e)
+    }
+}
+
+/// How `seal` encrypts a payload
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Encryption<'a> {
+    #[default]
+    None,
+    Passphrase(&'a str),
+    /// Anyone holding the identity of one of these recipients can decrypt
+    Recipients(&'a [Recipient]),
+}
+
+/// What `open` decrypts chunk data with
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Decryption<'a> {
+    #[default]
+    None,
+    Passphrase(&'a str),
+    Identity(&'a Identity),
+}
+
+/// Turns a payload into chunk data: frame, compress, then encrypt
+pub fn seal(payload: &Payload, encryption: &Encryption) -> Result<Vec<u8>, MessageError> {
+    let plaintext = payload.to_bytes()?;
+    Ok(match encryption {
+        Encryption::None => plaintext,
+        Encryption::Passphrase(passphrase) => crypto::encrypt(passphrase, &plaintext)?,
+        Encryption::Recipients(recipients) => crypto::encrypt_to_recipients(recipients, &plaintext)?,
+    })
+}
+
+/// Reverses `seal`, data that is not encrypted only needs `Decryption::None`
+pub fn open(data: &[u8], decryption: &Decryption) -> Result<Payload, MessageError> {
+    let plaintext = match decryption {
+        Decryption::Passphrase(passphrase) => crypto::decrypt(passphrase, data)?,
+        Decryption::Identity(identity) => crypto::decrypt_with_identity(identity, data)?,
+        Decryption::None => match crypto::envelope_version(data) {
+            Some(crypto::ENVELOPE_VERSION) => return Err(MessageError::Encrypted),
+            Some(crypto::RECIPIENT_ENVELOPE_VERSION) => return Err(MessageError::EncryptedToRecipients),
+            _ => data.to_vec(),
+        },
+    };
+    Ok(Payload::try_from(&plaintext[..])?)
+}
+
+/// Where and how `embed` stores a message
+#[derive(Debug, Clone, Copy)]
+pub struct EmbedOptions<'a> {
+    pub position: ChunkPosition,
+    /// Split the data into chunks of at most this many bytes, data too large for one chunk is
+    /// split even without it
+    pub fragment_size: Option<usize>,
+    /// Place a signature chunk after every chunk of the message
+    pub signer: Option<&'a SigningIdentity>,
+}
+
+impl Default for EmbedOptions<'_> {
+    fn default() -> Self {
+        EmbedOptions { position: ChunkPosition::BeforeIend, fragment_size: None, signer: None }
+    }
+}
+
+/// Where `embed` put a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Embedded {
+    /// Index of the first chunk of the message in the file
+    pub index: usize,
+    /// Number of chunks the message was split into
+    pub fragments: usize,
+}
+
+/// Stores sealed data in chunks of `chunk_type`
+///
+/// Fragments and their signatures are kept together, in order, starting at `options.position`.
+pub fn embed(png: &mut Png, chunk_type: &ChunkType, data: &[u8], options: &EmbedOptions) -> Result<Embedded, MessageError> {
+    let pieces = match options.fragment_size {
+        Some(size) => fragment::split(data, size)?,
+        None if data.len() > Chunk::MAX_LENGTH as usize => fragment::split(data, fragment::MAX_FRAGMENT_SIZE)?,
+        None => vec![data.to_vec()],
+    };
+    let fragments = pieces.len();
+    let mut first_index = None;
+    let mut position = options.position;
+    for piece in pieces {
+        let chunk = Chunk::new(*chunk_type, piece);
+        let signature = options.signer.map(|signer| signer.sign_chunk(&chunk));
+        let mut index = png.insert_chunk(chunk, position).map_err(MessageError::Placement)?;
+        first_index.get_or_insert(index);
+        if let Some(signature) = signature {
+            index += 1;
+            png.insert_chunk(signature, ChunkPosition::Index(index)).map_err(MessageError::Placement)?;
+        }
+        position = ChunkPosition::Index(index + 1);
+    }
+    Ok(Embedded { index: first_index.expect("there is at least one fragment"), fragments })
+}
+
+/// Collects the sealed data of the message starting at `chunks[index]`, reassembling it if it was split
+///
+/// `chunks` holds all chunks of one type, as found with `Png::chunks_by_type` or
+/// `PngRef::chunks_by_type`, or just their data.
+pub fn extract<C: AsRef<[u8]>>(chunks: &[C], index: usize) -> Result<Vec<u8>, MessageError> {
+    let data = chunks.get(index).ok_or(MessageError::IndexOutOfRange(index))?.as_ref();
+    if !fragment::is_fragment(data) {
+        return Ok(data.to_vec());
+    }
+    // The selected fragment goes first, so its message is the one reassembled
+    let others = chunks
+        .iter()
+        .enumerate()
+        .map(|(other, chunk)| (other, chunk.as_ref()))
+        .filter(|(other, data)| *other != index && fragment::is_fragment(data))
+        .map(|(_, data)| data);
+    Ok(fragment::reassemble(std::iter::once(data).chain(others))?)
+}
+
+/// Indices into `chunks` of the chunks that start a message, skipping later fragments of the same message
+///
+/// Like `extract`, this takes all chunks of one type or their data.
+pub fn message_starts<C: AsRef<[u8]>>(chunks: &[C]) -> Vec<usize> {
+    let mut seen = Vec::new();
+    let mut starts = Vec::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        if let Ok(fragment) = Fragment::try_from(chunk.as_ref()) {
+            if seen.contains(&fragment.id) {
+                continue;
+            }
+            seen.push(fragment.id);
+        }
+        starts.push(index);
+    }
+    starts
+}
+
+/// Indices into `chunks` of every chunk of the message the chunk at `index` belongs to
+///
+/// That is all fragments with the same message id in file order, or only `index` if it is not a fragment.
+/// Like `extract`, this takes all chunks of one type or their data.
+pub fn message_chunks<C: AsRef<[u8]>>(chunks: &[C], index: usize) -> Vec<usize> {
+    let id = match chunks.get(index).map(|chunk| Fragment::try_from(chunk.as_ref())) {
+        Some(Ok(fragment)) => fragment.id,
+        _ => return vec![index],
+    };
+    chunks
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| Fragment::try_from(chunk.as_ref()).is_ok_and(|fragment| fragment.id == id))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Whether the chunk at `index` is directly followed by a signature chunk
+pub fn is_signed(png: &Png, index: usize) -> bool {
+    png.chunks()
+        .get(index + 1)
+        .is_some_and(|next| next.chunk_type().matches(sign::SIGNATURE_CHUNK_TYPE))
+}
+
+/// Swaps the chunk at `index` for `chunk`, and its signature for a new one made by `signer`
+///
+/// The old signature no longer matches, so without a signer it is dropped. Returns whether that happened.
+pub fn replace(png: &mut Png, index: usize, chunk: Chunk, signer: Option<&SigningIdentity>) -> Result<bool, MessageError> {
+    let signature = signer.map(|signer| signer.sign_chunk(&chunk));
+    png.replace_chunk(index, chunk).map_err(|_| MessageError::IndexOutOfRange(index))?;
+    let signed = is_signed(png, index);
+    match (signature, signed) {
+        (Some(signature), true) => png.replace_chunk(index + 1, signature).map(|_| false),
+        (Some(signature), false) => png.insert_chunk(signature, ChunkPosition::Index(index + 1)).map(|_| false),
+        (None, true) => png.remove_chunk_at(index + 1).map(|_| true),
+        (None, false) => Ok(false),
+    }
+    .map_err(MessageError::Placement)
+}
+
+/// Removes the chunk at `index` together with its signature, if it has one
+pub fn remove(png: &mut Png, index: usize) -> Result<Chunk, MessageError> {
+    if is_signed(png, index) {
+        png.remove_chunk_at(index + 1).map_err(|_| MessageError::IndexOutOfRange(index + 1))?;
+    }
+    png.remove_chunk_at(index).map_err(|_| MessageError::IndexOutOfRange(index))
+}
+
+/// Checks the signature after the chunk at `index` and that it was made by one of `trusted` keys
+pub fn verify(png: &Png, index: usize, trusted: &[SignerKey]) -> Result<SignerKey, SignatureError> {
+    let chunk = png.chunks().get(index).ok_or(SignatureError::Missing)?;
+    let signature = png.chunks().get(index + 1).ok_or(SignatureError::Missing)?;
+    let signer = sign::verify_chunk(chunk, signature)?;
+    match trusted.contains(&signer) {
+        true => Ok(signer),
+        false => Err(SignatureError::Untrusted(signer.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::view::{ChunkRef, PngRef};
+    use std::str::FromStr;
+
+    fn png() -> Png {
+        let chunk = |chunk_type: &str| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), Vec::new());
+        Png::from_chunks(vec![chunk("IHDR"), chunk("IDAT"), chunk("IEND")])
+    }
+
+    fn chunk_type() -> ChunkType {
+        ChunkType::from_str("ruSt").unwrap()
+    }
+
+    #[test]
+    fn test_seal_and_open() {
+        let payload = Payload::text("a secret");
+        let sealed = seal(&payload, &Encryption::Passphrase("hunter2")).unwrap();
+        assert!(matches!(open(&sealed, &Decryption::None).unwrap_err(), MessageError::Encrypted));
+        assert_eq!(open(&sealed, &Decryption::Passphrase("hunter2")).unwrap(), payload);
+
+        let identity = Identity::generate();
+        let sealed = seal(&payload, &Encryption::Recipients(&[identity.recipient()])).unwrap();
+        assert!(matches!(open(&sealed, &Decryption::None).unwrap_err(), MessageError::EncryptedToRecipients));
+        assert_eq!(open(&sealed, &Decryption::Identity(&identity)).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_plaintext_is_not_mistaken_for_an_envelope() {
+        for text in ["\u{1}starts like a passphrase envelope", "\u{2}starts like a recipient envelope"] {
+            assert_eq!(open(text.as_bytes(), &Decryption::None).unwrap().data, text.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_embed_and_extract_fragments() {
+        let mut png = png();
+        let data: Vec<u8> = (0..100).collect();
+        let options = EmbedOptions { fragment_size: Some(30), ..EmbedOptions::default() };
+        let embedded = embed(&mut png, &chunk_type(), &data, &options).unwrap();
+        assert_eq!(embedded, Embedded { index: 2, fragments: 4 });
+        assert_eq!(png.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+
+        let chunks: Vec<&Chunk> = png.chunks_by_type("ruSt").collect();
+        assert_eq!(message_starts(&chunks), vec![0]);
+        assert_eq!(extract(&chunks, 2).unwrap(), data);
+        assert_eq!(message_chunks(&chunks, 2), [0, 1, 2, 3]);
+
+        // Borrowed views and plain data work the same
+        let bytes = png.as_bytes();
+        let view = PngRef::try_from(&bytes[..]).unwrap();
+        let refs: Vec<ChunkRef> = view.chunks_by_type("ruSt").collect();
+        assert_eq!(extract(&refs, 2).unwrap(), data);
+        let data_only: Vec<&[u8]> = refs.iter().map(|chunk| chunk.data()).collect();
+        assert_eq!(message_starts(&data_only), vec![0]);
+    }
+
+    #[test]
+    fn test_extract_the_same_payload_embedded_twice() {
+        let mut png = png();
+        let options = EmbedOptions { fragment_size: Some(4), ..EmbedOptions::default() };
+        embed(&mut png, &chunk_type(), b"same message", &options).unwrap();
+        embed(&mut png, &chunk_type(), b"same message", &options).unwrap();
+
+        let chunks: Vec<&Chunk> = png.chunks_by_type("ruSt").collect();
+        assert_eq!(chunks.len(), 6);
+        for index in 0..6 {
+            assert_eq!(extract(&chunks, index).unwrap(), b"same message", "index {}", index);
+        }
+        assert_eq!(message_starts(&chunks), [0, 3]);
+        assert_eq!(message_chunks(&chunks, 0), [0, 1, 2]);
+        assert_eq!(message_chunks(&chunks, 4), [3, 4, 5]);
+    }
+
+    #[test]
+    fn test_message_chunks_keep_to_one_payload() {
+        let mut png = png();
+        let options = EmbedOptions { fragment_size: Some(10), ..EmbedOptions::default() };
+        embed(&mut png, &chunk_type(), b"plain", &EmbedOptions::default()).unwrap();
+        embed(&mut png, &chunk_type(), &[1; 25], &options).unwrap();
+        embed(&mut png, &chunk_type(), &[2; 15], &options).unwrap();
+
+        let chunks: Vec<&[u8]> = png.chunks_by_type("ruSt").map(|chunk| chunk.data()).collect();
+        assert_eq!(message_chunks(&chunks, 0), [0]);
+        assert_eq!(message_chunks(&chunks, 2), [1, 2, 3]);
+        assert_eq!(message_chunks(&chunks, 5), [4, 5]);
+    }
+
+    #[test]
+    fn test_signatures_follow_their_chunk() {
+        let signer = SigningIdentity::generate();
+        let mut png = png();
+        let options = EmbedOptions { signer: Some(&signer), ..EmbedOptions::default() };
+        let index = embed(&mut png, &chunk_type(), b"signed", &options).unwrap().index;
+        assert!(is_signed(&png, index));
+        assert_eq!(verify(&png, index, &[signer.signer_key()]).unwrap(), signer.signer_key());
+
+        let unsigned = Chunk::new(chunk_type(), b"changed".to_vec());
+        assert!(replace(&mut png, index, unsigned, None).unwrap());
+        assert_eq!(verify(&png, index, &[signer.signer_key()]).unwrap_err(), SignatureError::Missing);
+
+        let resigned = Chunk::new(chunk_type(), b"changed again".to_vec());
+        assert!(!replace(&mut png, index, resigned, Some(&signer)).unwrap());
+        remove(&mut png, index).unwrap();
+        assert_eq!(png.chunks().len(), 3);
+    }
+}
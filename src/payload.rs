@@ -0,0 +1,409 @@
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use serde::Serialize;
+use std::fmt;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+/// Marks a framed payload, plain text messages are stored without a header and never start with NUL
+pub const MAGIC: [u8; 4] = *b"\0pmf";
+
+/// Version of the framing written by `Payload::to_bytes`
+pub const PAYLOAD_VERSION: u8 = 1;
+
+/// Upper bound for the size of compressed payloads and text once decompressed, so hostile
+/// payloads cannot exhaust memory. Uncompressed data is already in memory and not limited.
+pub const MAX_DECOMPRESSED_LENGTH: u64 = 64 * 1024 * 1024;
+
+/// The payload carries a file name and MIME type
+const FLAG_FILE: u8 = 1;
+/// The data is zlib-compressed
+const FLAG_DEFLATE: u8 = 2;
+/// The data is Zstandard-compressed
+const FLAG_ZSTD: u8 = 4;
+const KNOWN_FLAGS: u8 = FLAG_FILE | FLAG_DEFLATE | FLAG_ZSTD;
+
+// [MAGIC][VERSION][FLAGS][SIZE]
+const HEADER_LENGTH: usize = 4 + 1 + 1 + 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PayloadError {
+    /// The payload ends before its header does
+    Truncated,
+    /// The payload was framed by a newer version of pngme
+    UnsupportedVersion(u8),
+    /// The header contains a flag this version does not know
+    UnknownFlags(u8),
+    /// The stored size does not match the data that follows the header
+    SizeMismatch { expected: u64, actual: u64 },
+    /// A file name or MIME type is not valid UTF-8 or too long
+    InvalidMetadata(String),
+    /// The data could not be compressed or decompressed
+    Compression(String),
+    /// The payload uses a compression method this build does not support
+    UnsupportedCompression(Compression),
+    /// The stored size of compressed data exceeds `MAX_DECOMPRESSED_LENGTH`
+    TooLarge(u64),
+}
+
+impl std::error::Error for PayloadError {}
+
+impl fmt::Display for PayloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PayloadError::Truncated => write!(f, "Payload header is truncated"),
+            PayloadError::UnsupportedVersion(version) => write!(f, "Unsupported payload version {}", version),
+            PayloadError::UnknownFlags(flags) => write!(f, "Unknown payload flags {:#04x}", flags),
+            PayloadError::SizeMismatch { expected, actual } => {
+                write!(f, "Payload should be {} bytes but is {}", expected, actual)
+            }
+            PayloadError::InvalidMetadata(reason) => write!(f, "Invalid payload metadata: {}", reason),
+            PayloadError::Compression(reason) => write!(f, "Payload compression failed: {}", reason),
+            PayloadError::UnsupportedCompression(compression) => {
+                write!(f, "pngme was built without {} support, rebuild with --features {}", compression, compression)
+            }
+            PayloadError::TooLarge(size) => {
+                write!(f, "Payload of {} bytes exceeds the limit of {} bytes", size, MAX_DECOMPRESSED_LENGTH)
+            }
+        }
+    }
+}
+
+/// How the data of a payload is compressed before encryption
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    #[default]
+    None,
+    /// zlib, as used for PNG image data
+    Deflate,
+    /// Zstandard, only available with the `zstd` feature
+    Zstd,
+}
+
+impl Compression {
+    fn flag(&self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Deflate => FLAG_DEFLATE,
+            Compression::Zstd => FLAG_ZSTD,
+        }
+    }
+
+    fn from_flags(flags: u8) -> Result<Compression, PayloadError> {
+        match flags & (FLAG_DEFLATE | FLAG_ZSTD) {
+            0 => Ok(Compression::None),
+            FLAG_DEFLATE => Ok(Compression::Deflate),
+            FLAG_ZSTD => Ok(Compression::Zstd),
+            _ => Err(PayloadError::UnknownFlags(flags)),
+        }
+    }
+
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, PayloadError> {
+        let failed = |e: std::io::Error| PayloadError::Compression(e.to_string());
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data).and_then(|_| encoder.finish()).map_err(failed)
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => zstd::stream::encode_all(data, 0).map_err(failed),
+            #[cfg(not(feature = "zstd"))]
+            Compression::Zstd => Err(PayloadError::UnsupportedCompression(*self)),
+        }
+    }
+
+    /// Decompresses `data`, which must expand to exactly `size` bytes
+    fn decompress(&self, data: &[u8], size: u64) -> Result<Vec<u8>, PayloadError> {
+        let failed = |e: std::io::Error| PayloadError::Compression(e.to_string());
+        let mut decoded = Vec::new();
+        match self {
+            Compression::None => decoded.extend_from_slice(data),
+            Compression::Deflate => {
+                ZlibDecoder::new(data).take(size + 1).read_to_end(&mut decoded).map_err(failed)?;
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => {
+                zstd::stream::read::Decoder::new(data)
+                    .and_then(|decoder| decoder.take(size + 1).read_to_end(&mut decoded))
+                    .map_err(failed)?;
+            }
+            #[cfg(not(feature = "zstd"))]
+            Compression::Zstd => return Err(PayloadError::UnsupportedCompression(*self)),
+        }
+        if decoded.len() as u64 != size {
+            return Err(PayloadError::SizeMismatch { expected: size, actual: decoded.len() as u64 });
+        }
+        Ok(decoded)
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Compression::None => "none",
+            Compression::Deflate => "deflate",
+            Compression::Zstd => "zstd",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Compression {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "none" => Ok(Compression::None),
+            "deflate" | "zlib" => Ok(Compression::Deflate),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err("Compression must be none, deflate or zstd!"),
+        }
+    }
+}
+
+/// Name, size and type of an embedded file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileInfo {
+    /// File name without any directories
+    pub name: String,
+    pub size: u64,
+    pub mime: Option<String>,
+}
+
+/// What is stored in a message chunk before encryption
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Payload {
+    /// Set when the payload is an embedded file rather than a text message
+    pub file: Option<FileInfo>,
+    /// How `to_bytes` compresses the data, or how it was compressed when parsed
+    pub compression: Compression,
+    /// The uncompressed data
+    pub data: Vec<u8>,
+}
+
+impl Payload {
+    /// A text message, stored as is so older versions of pngme can still read it
+    pub fn text(message: &str) -> Payload {
+        Payload { file: None, compression: Compression::None, data: message.as_bytes().to_vec() }
+    }
+
+    pub fn file(name: &str, mime: Option<&str>, data: Vec<u8>) -> Payload {
+        let file = FileInfo { name: name.to_string(), size: data.len() as u64, mime: mime.map(str::to_string) };
+        Payload { file: Some(file), compression: Compression::None, data }
+    }
+
+    pub fn with_compression(self, compression: Compression) -> Payload {
+        Payload { compression, ..self }
+    }
+
+    /// Serializes the payload, dropping the compression if it would not make the data smaller
+    /// or the data is too large to be decompressed again
+    pub fn to_bytes(&self) -> Result<Vec<u8>, PayloadError> {
+        let compressed = match self.data.len() as u64 > MAX_DECOMPRESSED_LENGTH {
+            true => None,
+            false => Some(self.compression.compress(&self.data)?),
+        };
+        let (compression, data) = match compressed {
+            Some(compressed) if compressed.len() < self.data.len() => (self.compression, compressed),
+            _ => (Compression::None, self.data.clone()),
+        };
+        if self.file.is_none() && compression == Compression::None {
+            return Ok(data);
+        }
+
+        let mut bytes = Vec::with_capacity(HEADER_LENGTH + data.len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(PAYLOAD_VERSION);
+        bytes.push(compression.flag() | if self.file.is_some() { FLAG_FILE } else { 0 });
+        bytes.extend_from_slice(&(self.data.len() as u64).to_be_bytes());
+        if let Some(file) = &self.file {
+            let name_length = u16::try_from(file.name.len())
+                .map_err(|_| PayloadError::InvalidMetadata("file name is too long".to_string()))?;
+            let mime = file.mime.as_deref().unwrap_or("");
+            let mime_length = u8::try_from(mime.len())
+                .map_err(|_| PayloadError::InvalidMetadata("MIME type is too long".to_string()))?;
+            // [NAME LENGTH][NAME][MIME LENGTH][MIME]
+            bytes.extend_from_slice(&name_length.to_be_bytes());
+            bytes.extend_from_slice(file.name.as_bytes());
+            bytes.push(mime_length);
+            bytes.extend_from_slice(mime.as_bytes());
+        }
+        bytes.extend_from_slice(&data);
+        Ok(bytes)
+    }
+}
+
+impl TryFrom<&[u8]> for Payload {
+    type Error = PayloadError;
+
+    /// Parses a framed payload, anything without the magic bytes is taken to be a plain message
+    fn try_from(value: &[u8]) -> Result<Self, PayloadError> {
+        if !value.starts_with(&MAGIC) {
+            return Ok(Payload { file: None, compression: Compression::None, data: value.to_vec() });
+        }
+        let mut reader = Reader { bytes: &value[MAGIC.len()..] };
+        let version = reader.take(1)?[0];
+        if version != PAYLOAD_VERSION {
+            return Err(PayloadError::UnsupportedVersion(version));
+        }
+        let flags = reader.take(1)?[0];
+        if flags & !KNOWN_FLAGS != 0 {
+            return Err(PayloadError::UnknownFlags(flags));
+        }
+        let compression = Compression::from_flags(flags)?;
+        let size = u64::from_be_bytes(reader.take(8)?.try_into().unwrap());
+        if compression != Compression::None && size > MAX_DECOMPRESSED_LENGTH {
+            return Err(PayloadError::TooLarge(size));
+        }
+
+        let file = if flags & FLAG_FILE != 0 {
+            let name_length = u16::from_be_bytes(reader.take(2)?.try_into().unwrap()) as usize;
+            let name = utf8(reader.take(name_length)?, "file name")?;
+            let mime_length = reader.take(1)?[0] as usize;
+            let mime = utf8(reader.take(mime_length)?, "MIME type")?;
+            Some(FileInfo { name, size, mime: Some(mime).filter(|mime| !mime.is_empty()) })
+        } else {
+            None
+        };
+
+        let data = compression.decompress(reader.bytes, size)?;
+        Ok(Payload { file, compression, data })
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], PayloadError> {
+        if self.bytes.len() < length {
+            return Err(PayloadError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(taken)
+    }
+}
+
+fn utf8(bytes: &[u8], what: &str) -> Result<String, PayloadError> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| PayloadError::InvalidMetadata(format!("{} is not valid UTF-8", what)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_is_stored_as_is() {
+        let payload = Payload::text("hello");
+        assert_eq!(payload.to_bytes().unwrap(), b"hello");
+        assert_eq!(Payload::try_from(&b"hello"[..]).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let data: Vec<u8> = (0..=255).collect();
+        let payload = Payload::file("bytes.bin", Some("application/octet-stream"), data);
+        let bytes = payload.to_bytes().unwrap();
+        assert!(bytes.starts_with(&MAGIC));
+        assert_eq!(Payload::try_from(&bytes[..]).unwrap(), payload);
+
+        let payload = Payload::file("empty", None, Vec::new());
+        assert_eq!(Payload::try_from(&payload.to_bytes().unwrap()[..]).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_truncated_and_resized() {
+        let bytes = Payload::file("bytes.bin", None, vec![1, 2, 3]).to_bytes().unwrap();
+        for end in MAGIC.len()..bytes.len() - 3 {
+            assert!(Payload::try_from(&bytes[..end]).is_err(), "{}", end);
+        }
+        assert_eq!(
+            Payload::try_from(&bytes[..bytes.len() - 1]).unwrap_err(),
+            PayloadError::SizeMismatch { expected: 3, actual: 2 }
+        );
+    }
+
+    #[test]
+    fn test_compressed_round_trip() {
+        let text = "a fairly repetitive message ".repeat(100);
+        let payload = Payload::text(&text).with_compression(Compression::Deflate);
+        let bytes = payload.to_bytes().unwrap();
+        assert!(bytes.starts_with(&MAGIC));
+        assert!(bytes.len() < text.len());
+        assert_eq!(Payload::try_from(&bytes[..]).unwrap(), payload);
+
+        let file = Payload::file("text.txt", Some("text/plain"), text.into_bytes()).with_compression(Compression::Deflate);
+        assert_eq!(Payload::try_from(&file.to_bytes().unwrap()[..]).unwrap(), file);
+    }
+
+    #[test]
+    fn test_incompressible_data_is_stored_as_is() {
+        let payload = Payload::text("hi").with_compression(Compression::Deflate);
+        assert_eq!(payload.to_bytes().unwrap(), b"hi");
+    }
+
+    #[test]
+    fn test_size_is_enforced_after_decompression() {
+        let text = "x".repeat(1000);
+        let mut bytes = Payload::text(&text).with_compression(Compression::Deflate).to_bytes().unwrap();
+        bytes[6..14].copy_from_slice(&999u64.to_be_bytes());
+        assert_eq!(Payload::try_from(&bytes[..]).unwrap_err(), PayloadError::SizeMismatch { expected: 999, actual: 1000 });
+        bytes[6..14].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert_eq!(Payload::try_from(&bytes[..]).unwrap_err(), PayloadError::TooLarge(u64::MAX));
+    }
+
+    #[test]
+    fn test_decompression_bomb_is_cut_off() {
+        // A few dozen KiB that inflate to one byte more than the limit
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+        let zeros = vec![0; 1024 * 1024];
+        for _ in 0..MAX_DECOMPRESSED_LENGTH / zeros.len() as u64 {
+            encoder.write_all(&zeros).unwrap();
+        }
+        encoder.write_all(&[0]).unwrap();
+        let bomb = encoder.finish().unwrap();
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(PAYLOAD_VERSION);
+        bytes.push(FLAG_DEFLATE);
+        bytes.extend_from_slice(&(MAX_DECOMPRESSED_LENGTH + 1).to_be_bytes());
+        bytes.extend_from_slice(&bomb);
+        assert_eq!(Payload::try_from(&bytes[..]).unwrap_err(), PayloadError::TooLarge(MAX_DECOMPRESSED_LENGTH + 1));
+
+        // Lying about the size does not help, decompression stops right behind it
+        bytes[6..14].copy_from_slice(&MAX_DECOMPRESSED_LENGTH.to_be_bytes());
+        assert_eq!(
+            Payload::try_from(&bytes[..]).unwrap_err(),
+            PayloadError::SizeMismatch { expected: MAX_DECOMPRESSED_LENGTH, actual: MAX_DECOMPRESSED_LENGTH + 1 }
+        );
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_round_trip() {
+        let payload = Payload::text(&"zstd ".repeat(100)).with_compression(Compression::Zstd);
+        let bytes = payload.to_bytes().unwrap();
+        assert_eq!(bytes[5] & FLAG_ZSTD, FLAG_ZSTD);
+        assert_eq!(Payload::try_from(&bytes[..]).unwrap(), payload);
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn test_zstd_unavailable() {
+        let payload = Payload::text(&"zstd ".repeat(100)).with_compression(Compression::Zstd);
+        assert_eq!(payload.to_bytes().unwrap_err(), PayloadError::UnsupportedCompression(Compression::Zstd));
+    }
+
+    #[test]
+    fn test_unknown_version_and_flags() {
+        let mut bytes = Payload::file("bytes.bin", None, vec![1]).to_bytes().unwrap();
+        bytes[5] |= 0x80;
+        assert_eq!(Payload::try_from(&bytes[..]).unwrap_err(), PayloadError::UnknownFlags(0x81));
+        bytes[4] = 2;
+        assert_eq!(Payload::try_from(&bytes[..]).unwrap_err(), PayloadError::UnsupportedVersion(2));
+    }
+}
@@ -0,0 +1,315 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::PNGError;
+use crate::png::{ChunkEntry, Png};
+use crc::crc32::{self, Hasher32};
+use std::io::{self, Read, Write};
+
+/// Size of the buffer that `copy_data` streams chunk data through
+const COPY_BUFFER_LENGTH: usize = 64 * 1024;
+
+/// The length and type of a chunk, read before deciding what to do with its data
+#[derive(Debug)]
+pub struct ChunkHeader {
+    /// Byte offset of the chunk in the file
+    pub offset: usize,
+    pub length: u32,
+    pub chunk_type: ChunkType,
+}
+
+/// Reads the chunks of a PNG file one at a time from any `Read`
+///
+/// As an iterator it yields whole chunks and rejects CRC mismatches. To deal with large chunks
+/// such as `IDAT` without holding them in memory, call `next_header` and then `copy_data` or
+/// `skip_data`, which check the CRC as the data goes by. Dropping a header without reading its
+/// data skips it too. The signature is checked before the first chunk.
+///
+/// Reads are small, so wrap files in a `BufReader`.
+pub struct ChunkReader<R> {
+    reader: R,
+    /// Bytes consumed so far, the offset of whatever is read next
+    offset: usize,
+    signature_read: bool,
+    /// Offset and length of the last chunk whose header was returned
+    current: (usize, u32),
+    /// Data and CRC bytes of that chunk that have not been read yet
+    unread: u64,
+    /// Set once an error was returned, after which iteration stops
+    failed: bool,
+}
+
+impl<R: Read> ChunkReader<R> {
+    pub fn new(reader: R) -> Self {
+        ChunkReader { reader, offset: 0, signature_read: false, current: (0, 0), unread: 0, failed: false }
+    }
+
+    /// Number of bytes read from the underlying reader so far
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reads the length and type of the next chunk, or returns `None` at the end of the file
+    pub fn next_header(&mut self) -> Result<Option<ChunkHeader>, PNGError> {
+        if !self.signature_read {
+            self.read_signature()?;
+        }
+        if self.unread > 0 {
+            self.discard_unread()?;
+        }
+        let offset = self.offset;
+        let mut header = [0; 8];
+        let read = self.read_up_to(&mut header)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        // [LENGTH][TYPE][--DATA--][CRC]
+        if read < header.len() {
+            return Err(PNGError::Truncated { offset, needed: Chunk::METADATA_LENGTH, available: read });
+        }
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        if length > Chunk::MAX_LENGTH {
+            return Err(PNGError::InvalidLength { offset, length });
+        }
+        let chunk_type = ChunkType::try_from([header[4], header[5], header[6], header[7]])
+            .map_err(|e| e.offset_by(offset + 4))?;
+        self.current = (offset, length);
+        self.unread = u64::from(length) + 4;
+        Ok(Some(ChunkHeader { offset, length, chunk_type }))
+    }
+
+    /// Reads the data and CRC of the chunk `header` was returned for, without checking the CRC
+    pub fn read_data(&mut self, header: ChunkHeader) -> Result<ChunkEntry, PNGError> {
+        let mut data = Vec::new();
+        // Read through `take` so a huge declared length is not allocated up front
+        let read = (&mut self.reader)
+            .take(u64::from(header.length))
+            .read_to_end(&mut data)
+            .map_err(|e| PNGError::Io { offset: self.offset, kind: e.kind() })?;
+        self.offset += read;
+        self.unread -= read as u64;
+        let stored_crc = self.read_crc()?;
+        Ok(ChunkEntry { offset: header.offset, stored_crc, chunk: Chunk::new(header.chunk_type, data) })
+    }
+
+    /// Streams the data of the chunk `header` was returned for into `out`, checking its CRC
+    ///
+    /// The data is written before the CRC can be checked, so on `InvalidCrc` `out` already
+    /// holds all of it.
+    pub fn copy_data(&mut self, header: ChunkHeader, out: &mut impl Write) -> Result<(), PNGError> {
+        let mut digest = crc32::Digest::new(crc32::IEEE);
+        digest.write(&header.chunk_type.bytes());
+        let mut buffer = vec![0; COPY_BUFFER_LENGTH.min(header.length as usize)];
+        let mut remaining = header.length as usize;
+        while remaining > 0 {
+            let wanted = remaining.min(buffer.len());
+            let read = self.read_up_to(&mut buffer[..wanted])?;
+            self.unread -= read as u64;
+            if read < wanted {
+                return Err(self.truncated());
+            }
+            digest.write(&buffer[..read]);
+            out.write_all(&buffer[..read])
+                .map_err(|e| PNGError::Io { offset: self.offset, kind: e.kind() })?;
+            remaining -= read;
+        }
+        let stored_crc = self.read_crc()?;
+        if stored_crc != digest.sum32() {
+            return Err(PNGError::InvalidCrc { offset: header.offset, expected: digest.sum32(), actual: stored_crc });
+        }
+        Ok(())
+    }
+
+    /// Moves past the data of the chunk `header` was returned for, checking its CRC
+    pub fn skip_data(&mut self, header: ChunkHeader) -> Result<(), PNGError> {
+        self.copy_data(header, &mut io::sink())
+    }
+
+    /// Reads the next chunk with its offset and stored CRC, without checking the CRC
+    pub fn next_entry(&mut self) -> Result<Option<ChunkEntry>, PNGError> {
+        match self.next_header()? {
+            Some(header) => self.read_data(header).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn read_signature(&mut self) -> Result<(), PNGError> {
+        let mut signature = [0; 8];
+        let read = self.read_up_to(&mut signature)?;
+        if read < signature.len() {
+            return Err(PNGError::Truncated { offset: 0, needed: signature.len(), available: read });
+        }
+        if signature != Png::STANDARD_HEADER {
+            return Err(PNGError::InvalidSignature(signature));
+        }
+        self.signature_read = true;
+        Ok(())
+    }
+
+    fn read_crc(&mut self) -> Result<u32, PNGError> {
+        let mut crc = [0; 4];
+        let read = self.read_up_to(&mut crc)?;
+        self.unread -= read as u64;
+        if read < crc.len() {
+            return Err(self.truncated());
+        }
+        Ok(u32::from_be_bytes(crc))
+    }
+
+    /// Skips whatever is left of a chunk whose header was returned but whose data was not read
+    fn discard_unread(&mut self) -> Result<(), PNGError> {
+        let unread = self.unread;
+        let skipped = io::copy(&mut (&mut self.reader).take(unread), &mut io::sink())
+            .map_err(|e| PNGError::Io { offset: self.offset, kind: e.kind() })?;
+        self.offset += skipped as usize;
+        self.unread = 0;
+        if skipped < unread {
+            return Err(self.truncated());
+        }
+        Ok(())
+    }
+
+    /// Fills as much of `buffer` as the input allows, returning fewer bytes only at its end
+    fn read_up_to(&mut self, buffer: &mut [u8]) -> Result<usize, PNGError> {
+        let mut filled = 0;
+        while filled < buffer.len() {
+            match self.reader.read(&mut buffer[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(PNGError::Io { offset: self.offset + filled, kind: e.kind() }),
+            }
+        }
+        self.offset += filled;
+        Ok(filled)
+    }
+
+    /// The input ended inside the current chunk
+    fn truncated(&self) -> PNGError {
+        let (offset, length) = self.current;
+        PNGError::Truncated {
+            offset,
+            needed: length as usize + Chunk::METADATA_LENGTH,
+            available: self.offset - offset,
+        }
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk, PNGError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let entry = match self.next_entry() {
+            Ok(Some(entry)) if entry.crc_ok() => Ok(entry.chunk),
+            Ok(Some(entry)) => Err(PNGError::InvalidCrc {
+                offset: entry.offset,
+                expected: entry.chunk.crc(),
+                actual: entry.stored_crc,
+            }),
+            Ok(None) => return None,
+            Err(e) => Err(e),
+        };
+        self.failed = entry.is_err();
+        Some(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn file(chunks: &[(&str, &[u8])]) -> Vec<u8> {
+        let chunks = chunks
+            .iter()
+            .map(|(chunk_type, data)| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec()))
+            .collect();
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    /// Hands out at most three bytes per read, like a slow pipe
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let read = buf.len().min(3).min(self.0.len());
+            buf[..read].copy_from_slice(&self.0[..read]);
+            self.0 = &self.0[read..];
+            Ok(read)
+        }
+    }
+
+    #[test]
+    fn test_reads_chunks_in_order() {
+        let bytes = file(&[("IHDR", &[0; 13]), ("IDAT", b"pixels"), ("IEND", b"")]);
+        let chunks: Vec<Chunk> = ChunkReader::new(Trickle(&bytes)).collect::<Result<_, _>>().unwrap();
+        let types: Vec<&str> = chunks.iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "IDAT", "IEND"]);
+        assert_eq!(chunks[1].data(), b"pixels");
+    }
+
+    #[test]
+    fn test_skip_and_copy_data() {
+        let pixels: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        let bytes = file(&[("IDAT", &pixels), ("IDAT", &pixels), ("ruSt", b"message"), ("IEND", b"")]);
+        let mut reader = ChunkReader::new(&bytes[..]);
+
+        let first = reader.next_header().unwrap().unwrap();
+        assert_eq!((first.offset, first.length), (8, 200_000));
+        reader.skip_data(first).unwrap();
+        let mut copied = Vec::new();
+        let second = reader.next_header().unwrap().unwrap();
+        reader.copy_data(second, &mut copied).unwrap();
+        assert_eq!(copied, pixels);
+
+        // A header dropped without reading its data is skipped by the next call
+        assert_eq!(reader.next_header().unwrap().unwrap().chunk_type.to_string(), "ruSt");
+        assert_eq!(reader.next_header().unwrap().unwrap().chunk_type.to_string(), "IEND");
+        assert!(reader.next_header().unwrap().is_none());
+        assert_eq!(reader.offset(), bytes.len());
+    }
+
+    #[test]
+    fn test_crc_mismatch() {
+        let mut bytes = file(&[("IDAT", b"pixels"), ("IEND", b"")]);
+        bytes[8 + 8] ^= 0xff;
+        let mut reader = ChunkReader::new(&bytes[..]);
+        let header = reader.next_header().unwrap().unwrap();
+        assert!(matches!(reader.skip_data(header).unwrap_err(), PNGError::InvalidCrc { offset: 8, .. }));
+
+        let mut chunks = ChunkReader::new(&bytes[..]);
+        assert!(matches!(chunks.next(), Some(Err(PNGError::InvalidCrc { offset: 8, .. }))));
+        assert!(chunks.next().is_none());
+    }
+
+    #[test]
+    fn test_truncated_data() {
+        let bytes = file(&[("IDAT", &[7; 100]), ("IEND", b"")]);
+        let mut reader = ChunkReader::new(&bytes[..60]);
+        let header = reader.next_header().unwrap().unwrap();
+        assert_eq!(
+            reader.copy_data(header, &mut io::sink()).unwrap_err(),
+            PNGError::Truncated { offset: 8, needed: 112, available: 52 }
+        );
+    }
+
+    #[test]
+    fn test_read_errors_are_reported() {
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::from(io::ErrorKind::PermissionDenied))
+            }
+        }
+        assert_eq!(
+            ChunkReader::new(Failing).next_header().unwrap_err(),
+            PNGError::Io { offset: 0, kind: io::ErrorKind::PermissionDenied }
+        );
+    }
+}
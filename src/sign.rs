@@ -0,0 +1,241 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::crypto::{self, CryptoError, KEY_LENGTH};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey, SIGNATURE_LENGTH};
+use std::fmt;
+use std::str::FromStr;
+
+/// Type of the companion chunk that carries a signature, placed right after the chunk it signs
+pub const SIGNATURE_CHUNK_TYPE: &str = "siGn";
+
+/// First byte of every signature chunk
+pub const SIGNATURE_VERSION: u8 = 1;
+
+// [VERSION][SIGNED CHUNK TYPE][PUBLIC KEY][SIGNATURE]
+const SIGNATURE_DATA_LENGTH: usize = 1 + 4 + KEY_LENGTH + SIGNATURE_LENGTH;
+/// Prepended to the signed bytes so these signatures cannot be replayed in another protocol
+const SIGNATURE_CONTEXT: &[u8] = b"pngme ed25519 v1\0";
+
+const PUBLIC_KEY_PREFIX: &str = "pngme-ed25519-pub-";
+const SECRET_KEY_PREFIX: &str = "pngme-ed25519-sec-";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureError {
+    /// The chunk has no signature chunk after it
+    Missing,
+    /// The signature chunk could not be parsed
+    Malformed(String),
+    /// The signature chunk belongs to a chunk of a different type
+    TypeMismatch { expected: String, found: String },
+    /// The signature does not match the chunk type and payload
+    Invalid,
+    /// The signature is valid but was made by a key that is not trusted
+    Untrusted(String),
+}
+
+impl std::error::Error for SignatureError {}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureError::Missing => write!(f, "The chunk is not signed"),
+            SignatureError::Malformed(reason) => write!(f, "Malformed signature chunk: {}", reason),
+            SignatureError::TypeMismatch { expected, found } => {
+                write!(f, "The signature is for a {} chunk, not {}", found, expected)
+            }
+            SignatureError::Invalid => write!(f, "The signature does not match, the chunk has been modified"),
+            SignatureError::Untrusted(signer) => write!(f, "The chunk was signed by an untrusted key {}", signer),
+        }
+    }
+}
+
+/// An Ed25519 public key that signatures are checked against
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SignerKey(VerifyingKey);
+
+impl fmt::Display for SignerKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", crypto::encode_key(self.0.as_bytes(), PUBLIC_KEY_PREFIX))
+    }
+}
+
+impl fmt::Debug for SignerKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SignerKey({})", self)
+    }
+}
+
+impl FromStr for SignerKey {
+    type Err = CryptoError;
+
+    fn from_str(s: &str) -> Result<Self, CryptoError> {
+        let bytes = crypto::decode_key(s.trim(), PUBLIC_KEY_PREFIX)?;
+        VerifyingKey::from_bytes(&bytes)
+            .map(SignerKey)
+            .map_err(|_| CryptoError::InvalidKey("not a valid Ed25519 public key".to_string()))
+    }
+}
+
+/// An Ed25519 private key used to sign chunks
+///
+/// Neither `Display` nor `Debug` reveal the key, use `to_secret_string` to store it.
+#[derive(Clone)]
+pub struct SigningIdentity(SigningKey);
+
+impl SigningIdentity {
+    pub fn generate() -> SigningIdentity {
+        let mut bytes = [0u8; KEY_LENGTH];
+        OsRng.fill_bytes(&mut bytes);
+        SigningIdentity(SigningKey::from_bytes(&bytes))
+    }
+
+    pub fn signer_key(&self) -> SignerKey {
+        SignerKey(self.0.verifying_key())
+    }
+
+    pub fn to_secret_string(&self) -> String {
+        crypto::encode_key(&self.0.to_bytes(), SECRET_KEY_PREFIX)
+    }
+
+    /// Builds the signature chunk for `chunk`, to be placed directly after it
+    pub fn sign_chunk(&self, chunk: &Chunk) -> Chunk {
+        let chunk_type = chunk.chunk_type().bytes();
+        let signature = self.0.sign(&signed_bytes(&chunk_type, chunk.data()));
+        let mut data = Vec::with_capacity(SIGNATURE_DATA_LENGTH);
+        data.push(SIGNATURE_VERSION);
+        data.extend_from_slice(&chunk_type);
+        data.extend_from_slice(self.0.verifying_key().as_bytes());
+        data.extend_from_slice(&signature.to_bytes());
+        let signature_type = ChunkType::from_str(SIGNATURE_CHUNK_TYPE).expect("signature chunk type is valid");
+        Chunk::new(signature_type, data)
+    }
+}
+
+impl fmt::Debug for SigningIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SigningIdentity({})", self.signer_key())
+    }
+}
+
+impl FromStr for SigningIdentity {
+    type Err = CryptoError;
+
+    /// Parses the contents of a signing key file, skipping blank lines and `#` comments
+    fn from_str(s: &str) -> Result<Self, CryptoError> {
+        let bytes = crypto::decode_key(crypto::key_line(s)?, SECRET_KEY_PREFIX)?;
+        Ok(SigningIdentity(SigningKey::from_bytes(&bytes)))
+    }
+}
+
+/// Checks that `signature` is a valid signature chunk for `chunk` and returns the key that made it
+///
+/// This only proves the chunk is unchanged since it was signed, callers still have to decide
+/// whether they trust the returned key.
+pub fn verify_chunk(chunk: &Chunk, signature: &Chunk) -> Result<SignerKey, SignatureError> {
+    if signature.chunk_type().to_string() != SIGNATURE_CHUNK_TYPE {
+        return Err(SignatureError::Missing);
+    }
+    let data = signature.data();
+    if data.len() != SIGNATURE_DATA_LENGTH {
+        return Err(SignatureError::Malformed(format!(
+            "expected {} bytes, found {}",
+            SIGNATURE_DATA_LENGTH,
+            data.len()
+        )));
+    }
+    if data[0] != SIGNATURE_VERSION {
+        return Err(SignatureError::Malformed(format!("unsupported version {}", data[0])));
+    }
+    let chunk_type = chunk.chunk_type().bytes();
+    if data[1..5] != chunk_type {
+        return Err(SignatureError::TypeMismatch {
+            expected: chunk.chunk_type().to_string().to_owned(),
+            found: String::from_utf8_lossy(&data[1..5]).into_owned(),
+        });
+    }
+    let public_key: [u8; KEY_LENGTH] = data[5..5 + KEY_LENGTH].try_into().unwrap();
+    let signer = VerifyingKey::from_bytes(&public_key)
+        .map_err(|_| SignatureError::Malformed("invalid public key".to_string()))?;
+    let signature = Signature::from_bytes(data[5 + KEY_LENGTH..].try_into().unwrap());
+    // Strict verification also rejects small order keys and non-canonical signatures
+    signer
+        .verify_strict(&signed_bytes(&chunk_type, chunk.data()), &signature)
+        .map_err(|_| SignatureError::Invalid)?;
+    Ok(SignerKey(signer))
+}
+
+fn signed_bytes(chunk_type: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(SIGNATURE_CONTEXT.len() + chunk_type.len() + data.len());
+    bytes.extend_from_slice(SIGNATURE_CONTEXT);
+    bytes.extend_from_slice(chunk_type);
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let identity = SigningIdentity::generate();
+        let message = chunk("ruSt", b"signed message");
+        let signature = identity.sign_chunk(&message);
+        assert_eq!(signature.chunk_type().to_string(), SIGNATURE_CHUNK_TYPE);
+        assert_eq!(verify_chunk(&message, &signature).unwrap(), identity.signer_key());
+    }
+
+    #[test]
+    fn test_modified_chunk_is_rejected() {
+        let identity = SigningIdentity::generate();
+        let signature = identity.sign_chunk(&chunk("ruSt", b"signed message"));
+        let modified = chunk("ruSt", b"signed messagE");
+        assert_eq!(verify_chunk(&modified, &signature).unwrap_err(), SignatureError::Invalid);
+
+        let retyped = chunk("ruSx", b"signed message");
+        assert!(matches!(verify_chunk(&retyped, &signature).unwrap_err(), SignatureError::TypeMismatch { .. }));
+    }
+
+    #[test]
+    fn test_malformed_signature() {
+        let message = chunk("ruSt", b"signed message");
+        assert_eq!(verify_chunk(&message, &chunk("teXt", b"")).unwrap_err(), SignatureError::Missing);
+        assert!(matches!(
+            verify_chunk(&message, &chunk(SIGNATURE_CHUNK_TYPE, b"short")).unwrap_err(),
+            SignatureError::Malformed(_)
+        ));
+    }
+
+    #[test]
+    fn test_small_order_key_is_rejected() {
+        // The identity point as key and as R with s = 0 satisfies the plain verification
+        // equation for any message
+        let mut identity_point = [0u8; KEY_LENGTH];
+        identity_point[0] = 1;
+        let mut data = vec![SIGNATURE_VERSION];
+        data.extend_from_slice(b"ruSt");
+        data.extend_from_slice(&identity_point);
+        data.extend_from_slice(&identity_point);
+        data.extend_from_slice(&[0; 32]);
+        let message = chunk("ruSt", b"never signed");
+        let signature = chunk(SIGNATURE_CHUNK_TYPE, &data);
+        assert_eq!(verify_chunk(&message, &signature).unwrap_err(), SignatureError::Invalid);
+    }
+
+    #[test]
+    fn test_key_strings() {
+        let identity = SigningIdentity::generate();
+        let signer = identity.signer_key();
+        assert_eq!(SignerKey::from_str(&signer.to_string()).unwrap(), signer);
+
+        let file = format!("# public key: {}\n{}\n", signer, identity.to_secret_string());
+        assert_eq!(SigningIdentity::from_str(&file).unwrap().signer_key(), signer);
+        assert!(!format!("{:?}", identity).contains(&identity.to_secret_string()));
+    }
+}
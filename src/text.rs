@@ -0,0 +1,335 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::PNGError;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use std::fmt;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+pub use crate::payload::MAX_DECOMPRESSED_LENGTH;
+
+/// Which of the three textual chunk types an entry is stored in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextKind {
+    /// `tEXt`: uncompressed Latin-1
+    Text,
+    /// `zTXt`: zlib-compressed Latin-1
+    Compressed,
+    /// `iTXt`: UTF-8 with language tag and translated keyword, optionally compressed
+    International,
+}
+
+impl TextKind {
+    pub fn chunk_type(&self) -> &'static str {
+        match self {
+            TextKind::Text => "tEXt",
+            TextKind::Compressed => "zTXt",
+            TextKind::International => "iTXt",
+        }
+    }
+
+    pub fn from_chunk_type(chunk_type: &str) -> Option<TextKind> {
+        match chunk_type {
+            "tEXt" => Some(TextKind::Text),
+            "zTXt" => Some(TextKind::Compressed),
+            "iTXt" => Some(TextKind::International),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for TextKind {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "text" | "tEXt" => Ok(TextKind::Text),
+            "ztxt" | "zTXt" => Ok(TextKind::Compressed),
+            "itxt" | "iTXt" => Ok(TextKind::International),
+            _ => Err("Text kind must be text, ztxt or itxt!"),
+        }
+    }
+}
+
+/// A keyword/value pair stored in a `tEXt`, `zTXt` or `iTXt` chunk
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TextEntry {
+    pub kind: TextKind,
+    pub keyword: String,
+    pub text: String,
+    /// RFC 3066 language tag, only stored in `iTXt`
+    pub language_tag: String,
+    /// Keyword translated into the language of `language_tag`, only stored in `iTXt`
+    pub translated_keyword: String,
+    /// Whether the text is zlib-compressed, always true for `zTXt`
+    pub compressed: bool,
+}
+
+impl TextEntry {
+    /// An uncompressed `tEXt` entry
+    pub fn text(keyword: &str, text: &str) -> Self {
+        TextEntry::new(TextKind::Text, keyword, text)
+    }
+
+    /// A compressed `zTXt` entry
+    pub fn compressed(keyword: &str, text: &str) -> Self {
+        TextEntry {
+            compressed: true,
+            ..TextEntry::new(TextKind::Compressed, keyword, text)
+        }
+    }
+
+    /// An `iTXt` entry, which may hold any UTF-8 text
+    pub fn international(
+        keyword: &str,
+        text: &str,
+        language_tag: &str,
+        translated_keyword: &str,
+        compressed: bool,
+    ) -> Self {
+        TextEntry {
+            language_tag: language_tag.to_string(),
+            translated_keyword: translated_keyword.to_string(),
+            compressed,
+            ..TextEntry::new(TextKind::International, keyword, text)
+        }
+    }
+
+    fn new(kind: TextKind, keyword: &str, text: &str) -> Self {
+        TextEntry {
+            kind,
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+            language_tag: String::new(),
+            translated_keyword: String::new(),
+            compressed: false,
+        }
+    }
+
+    /// Encodes the entry into a chunk, checking the keyword and character set rules of the spec
+    pub fn to_chunk(&self) -> Result<Chunk, PNGError> {
+        let mut data = encode_keyword(&self.keyword)?;
+        data.push(0);
+        match self.kind {
+            TextKind::Text => data.extend(encode_latin1(&self.text)?),
+            TextKind::Compressed => {
+                data.push(0);
+                data.extend(compress(&encode_latin1(&self.text)?)?);
+            }
+            TextKind::International => {
+                if !self.language_tag.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') {
+                    return Err(invalid("language tag may only contain ASCII letters, digits and hyphens"));
+                }
+                data.push(self.compressed as u8);
+                data.push(0);
+                data.extend(self.language_tag.as_bytes());
+                data.push(0);
+                if self.translated_keyword.contains('\0') {
+                    return Err(invalid("translated keyword must not contain null bytes"));
+                }
+                data.extend(self.translated_keyword.as_bytes());
+                data.push(0);
+                if self.compressed {
+                    data.extend(compress(self.text.as_bytes())?);
+                } else {
+                    data.extend(self.text.as_bytes());
+                }
+            }
+        }
+        let chunk_type = ChunkType::from_str(self.kind.chunk_type())?;
+        Ok(Chunk::new(chunk_type, data))
+    }
+}
+
+impl TryFrom<&Chunk> for TextEntry {
+    type Error = PNGError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, PNGError> {
+        let kind = match TextKind::from_chunk_type(chunk.chunk_type().to_string()) {
+            Some(kind) => kind,
+            None => return Err(invalid(&format!("{} is not a text chunk", chunk.chunk_type()))),
+        };
+        let (keyword, rest) = split_null(chunk.data(), "keyword")?;
+        let keyword = decode_latin1(keyword);
+        validate_keyword(&keyword)?;
+        match kind {
+            TextKind::Text => Ok(TextEntry::text(&keyword, &decode_latin1(rest))),
+            TextKind::Compressed => match rest.split_first() {
+                Some((0, compressed)) => Ok(TextEntry::compressed(&keyword, &decode_latin1(&decompress(compressed)?))),
+                Some(_) => Err(invalid("unknown compression method")),
+                None => Err(invalid("missing compression method")),
+            },
+            TextKind::International => {
+                if rest.len() < 2 {
+                    return Err(invalid("missing compression flag and method"));
+                }
+                let compressed = match (rest[0], rest[1]) {
+                    (0, _) => false,
+                    (1, 0) => true,
+                    (1, _) => return Err(invalid("unknown compression method")),
+                    _ => return Err(invalid("compression flag must be 0 or 1")),
+                };
+                let (language_tag, rest) = split_null(&rest[2..], "language tag")?;
+                let (translated_keyword, text) = split_null(rest, "translated keyword")?;
+                let text = if compressed { decompress(text)? } else { text.to_vec() };
+                Ok(TextEntry::international(
+                    &keyword,
+                    &utf8(text, "text")?,
+                    &utf8(language_tag.to_vec(), "language tag")?,
+                    &utf8(translated_keyword.to_vec(), "translated keyword")?,
+                    compressed,
+                ))
+            }
+        }
+    }
+}
+
+impl fmt::Display for TextEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}", self.keyword, self.kind.chunk_type())?;
+        if !self.language_tag.is_empty() {
+            write!(f, ", {}", self.language_tag)?;
+        }
+        if !self.translated_keyword.is_empty() {
+            write!(f, ", {}", self.translated_keyword)?;
+        }
+        write!(f, "]: {}", self.text)
+    }
+}
+
+/// Keywords are 1-79 printable Latin-1 characters without leading, trailing or repeated spaces
+fn validate_keyword(keyword: &str) -> Result<(), PNGError> {
+    let length = keyword.chars().count();
+    if length == 0 || length > 79 {
+        return Err(invalid("keyword must be 1 to 79 characters long"));
+    }
+    if !keyword.chars().all(|c| (' '..='~').contains(&c) || ('\u{a1}'..='\u{ff}').contains(&c)) {
+        return Err(invalid("keyword may only contain printable Latin-1 characters"));
+    }
+    if keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        return Err(invalid("keyword must not have leading, trailing or consecutive spaces"));
+    }
+    Ok(())
+}
+
+fn encode_keyword(keyword: &str) -> Result<Vec<u8>, PNGError> {
+    validate_keyword(keyword)?;
+    encode_latin1(keyword)
+}
+
+fn encode_latin1(text: &str) -> Result<Vec<u8>, PNGError> {
+    text.chars()
+        .map(|c| match u8::try_from(u32::from(c)) {
+            Ok(byte) if byte != 0 => Ok(byte),
+            _ => Err(invalid(&format!("{:?} cannot be stored as Latin-1, use itxt instead", c))),
+        })
+        .collect()
+}
+
+fn decode_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| char::from(b)).collect()
+}
+
+fn utf8(bytes: Vec<u8>, field: &str) -> Result<String, PNGError> {
+    String::from_utf8(bytes).map_err(|_| invalid(&format!("{} is not valid UTF-8", field)))
+}
+
+fn split_null<'a>(bytes: &'a [u8], field: &str) -> Result<(&'a [u8], &'a [u8]), PNGError> {
+    match bytes.iter().position(|&b| b == 0) {
+        Some(index) => Ok((&bytes[..index], &bytes[index + 1..])),
+        None => Err(invalid(&format!("{} is not null-terminated", field))),
+    }
+}
+
+fn compress(bytes: &[u8]) -> Result<Vec<u8>, PNGError> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(bytes)
+        .and_then(|_| encoder.finish())
+        .map_err(|e| invalid(&format!("compression failed: {}", e)))
+}
+
+fn decompress(bytes: &[u8]) -> Result<Vec<u8>, PNGError> {
+    let mut decoded = Vec::new();
+    ZlibDecoder::new(bytes)
+        .take(MAX_DECOMPRESSED_LENGTH + 1)
+        .read_to_end(&mut decoded)
+        .map_err(|e| invalid(&format!("decompression failed: {}", e)))?;
+    if decoded.len() as u64 > MAX_DECOMPRESSED_LENGTH {
+        return Err(invalid("decompressed text is too large"));
+    }
+    Ok(decoded)
+}
+
+fn invalid(reason: &str) -> PNGError {
+    PNGError::InvalidText(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(entry: &TextEntry) -> TextEntry {
+        TextEntry::try_from(&entry.to_chunk().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let entry = TextEntry::text("Comment", "Caf\u{e9} au lait");
+        let chunk = entry.to_chunk().unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "tEXt");
+        assert_eq!(chunk.data(), b"Comment\0Caf\xe9 au lait");
+        assert_eq!(round_trip(&entry), entry);
+    }
+
+    #[test]
+    fn test_compressed_round_trip() {
+        let entry = TextEntry::compressed("Description", &"long text ".repeat(100));
+        let chunk = entry.to_chunk().unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "zTXt");
+        assert!(chunk.length() < 200);
+        assert_eq!(round_trip(&entry), entry);
+    }
+
+    #[test]
+    fn test_international_round_trip() {
+        for compressed in [false, true] {
+            let entry = TextEntry::international("Title", "\u{65e5}\u{672c}", "ja", "\u{984c}", compressed);
+            let chunk = entry.to_chunk().unwrap();
+            assert_eq!(chunk.chunk_type().to_string(), "iTXt");
+            assert_eq!(round_trip(&entry), entry);
+        }
+    }
+
+    #[test]
+    fn test_latin1_rejects_other_characters() {
+        assert!(TextEntry::text("Title", "\u{65e5}").to_chunk().is_err());
+        assert!(TextEntry::compressed("Title", "\u{65e5}").to_chunk().is_err());
+    }
+
+    #[test]
+    fn test_invalid_keywords() {
+        for keyword in ["", " Title", "Title ", "Ti  tle", "Tit\u{1}le", &"k".repeat(80)] {
+            assert!(TextEntry::text(keyword, "text").to_chunk().is_err(), "{:?}", keyword);
+        }
+    }
+
+    #[test]
+    fn test_malformed_chunks() {
+        for (chunk_type, data) in [
+            ("tEXt", b"no terminator".to_vec()),
+            ("zTXt", b"Title\0\x01abc".to_vec()),
+            ("zTXt", b"Title\0\0not zlib".to_vec()),
+            ("iTXt", b"Title\0\x02\0\0\0text".to_vec()),
+            ("iTXt", b"Title\0\0\0en".to_vec()),
+            ("ruSt", b"Title\0text".to_vec()),
+        ] {
+            let chunk = Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data);
+            assert!(TextEntry::try_from(&chunk).is_err());
+        }
+    }
+}
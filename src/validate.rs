@@ -0,0 +1,243 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::view::ChunkRef;
+use serde::Serialize;
+use std::fmt;
+
+/// Ancillary chunks that must come before both `PLTE` and the first `IDAT`
+const BEFORE_PLTE: [&str; 5] = ["cHRM", "gAMA", "iCCP", "sBIT", "sRGB"];
+/// Ancillary chunks that must come after `PLTE` but before the first `IDAT`
+const AFTER_PLTE: [&str; 3] = ["bKGD", "hIST", "tRNS"];
+/// Ancillary chunks that must come before the first `IDAT`
+const BEFORE_IDAT: [&str; 2] = ["pHYs", "sPLT"];
+/// Chunks that may appear at most once
+const UNIQUE: [&str; 13] = [
+    "IHDR", "PLTE", "IEND", "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "bKGD", "hIST", "tRNS", "pHYs", "tIME",
+];
+/// Critical chunks defined by the spec, any other critical chunk cannot be decoded
+const KNOWN_CRITICAL: [&str; 4] = ["IHDR", "PLTE", "IDAT", "IEND"];
+
+/// A single breach of the PNG spec's chunk rules
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Violation {
+    /// Index of the offending chunk, or `None` if the problem is a missing chunk
+    pub index: Option<usize>,
+    pub chunk_type: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.index, &self.chunk_type) {
+            (Some(index), Some(chunk_type)) => write!(f, "chunk {} ({}): {}", index, chunk_type, self.message),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Checks chunk ordering and multiplicity against the PNG spec and returns every violation found
+pub fn validate<'a>(chunks: impl IntoIterator<Item = &'a Chunk>) -> Vec<Violation> {
+    let chunks: Vec<(ChunkType, &[u8])> = chunks.into_iter().map(|chunk| (*chunk.chunk_type(), chunk.data())).collect();
+    validate_parts(&chunks)
+}
+
+/// Like `validate`, for chunks borrowed from a buffer
+pub fn validate_refs<'a>(chunks: impl IntoIterator<Item = ChunkRef<'a>>) -> Vec<Violation> {
+    let chunks: Vec<(ChunkType, &[u8])> = chunks.into_iter().map(|chunk| (chunk.chunk_type(), chunk.data())).collect();
+    validate_parts(&chunks)
+}
+
+fn validate_parts(chunks: &[(ChunkType, &[u8])]) -> Vec<Violation> {
+    let types: Vec<&str> = chunks.iter().map(|(chunk_type, _)| chunk_type.to_string()).collect();
+    let mut violations = Vec::new();
+    let mut at = |index: usize, message: String| {
+        violations.push(Violation {
+            index: Some(index),
+            chunk_type: Some(types[index].to_string()),
+            message,
+        })
+    };
+
+    let first_idat = types.iter().position(|t| *t == "IDAT");
+    let plte = types.iter().position(|t| *t == "PLTE");
+    let mut header = None;
+
+    for (index, chunk_type) in types.iter().enumerate() {
+        match *chunk_type {
+            "IHDR" => {
+                if index != 0 {
+                    at(index, "IHDR must be the first chunk".to_string());
+                } else {
+                    match Ihdr::try_from(chunks[index].1) {
+                        Ok(ihdr) => header = Some(ihdr),
+                        Err(e) => at(index, e.to_string()),
+                    }
+                }
+            }
+            "IEND" => {
+                if index != types.len() - 1 {
+                    at(index, "IEND must be the last chunk".to_string());
+                }
+                if !chunks[index].1.is_empty() {
+                    at(index, "IEND must be empty".to_string());
+                }
+            }
+            "IDAT" if Some(index) != first_idat && types[index - 1] != "IDAT" => {
+                at(index, "IDAT chunks must be consecutive".to_string());
+            }
+            "PLTE" if first_idat.is_some_and(|idat| index > idat) => {
+                at(index, "PLTE must come before the first IDAT".to_string());
+            }
+            _ => {}
+        }
+
+        if BEFORE_PLTE.contains(chunk_type) {
+            if plte.is_some_and(|plte| index > plte) {
+                at(index, format!("{} must come before PLTE", chunk_type));
+            }
+            if first_idat.is_some_and(|idat| index > idat) {
+                at(index, format!("{} must come before the first IDAT", chunk_type));
+            }
+        }
+        if AFTER_PLTE.contains(chunk_type) {
+            if plte.is_some_and(|plte| index < plte) {
+                at(index, format!("{} must come after PLTE", chunk_type));
+            }
+            if first_idat.is_some_and(|idat| index > idat) {
+                at(index, format!("{} must come before the first IDAT", chunk_type));
+            }
+        }
+        if BEFORE_IDAT.contains(chunk_type) && first_idat.is_some_and(|idat| index > idat) {
+            at(index, format!("{} must come before the first IDAT", chunk_type));
+        }
+        if UNIQUE.contains(chunk_type) && types[..index].contains(chunk_type) {
+            at(index, format!("{} may only appear once", chunk_type));
+        }
+        if chunks[index].0.is_critical() && !KNOWN_CRITICAL.contains(chunk_type) {
+            at(index, format!("unknown critical chunk {}", chunk_type));
+        }
+        if !chunks[index].0.is_reserved_bit_valid() {
+            at(index, "reserved bit of the chunk type is set".to_string());
+        }
+    }
+
+    if let (Some(srgb), true) = (types.iter().position(|t| *t == "sRGB"), types.contains(&"iCCP")) {
+        at(srgb, "sRGB and iCCP must not both be present".to_string());
+    }
+    if let (Some(header), Some(plte)) = (&header, plte) {
+        if matches!(header.color_type, ColorType::Grayscale | ColorType::GrayscaleAlpha) {
+            at(plte, format!("PLTE is not allowed for {} images", header.color_type));
+        }
+    }
+
+    let mut missing = |message: &str| {
+        violations.push(Violation { index: None, chunk_type: None, message: message.to_string() })
+    };
+    if !types.contains(&"IHDR") {
+        missing("missing IHDR chunk");
+    }
+    if first_idat.is_none() {
+        missing("missing IDAT chunk");
+    }
+    if !types.contains(&"IEND") {
+        missing("missing IEND chunk");
+    }
+    if plte.is_none() && header.is_some_and(|h| h.color_type == ColorType::Indexed) {
+        missing("missing PLTE chunk required for indexed images");
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use crate::ihdr::Interlace;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str) -> Chunk {
+        let data = match chunk_type {
+            "IHDR" => ihdr(ColorType::Rgb).as_bytes(),
+            "IEND" => Vec::new(),
+            _ => vec![0; 3],
+        };
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    fn ihdr(color_type: ColorType) -> Ihdr {
+        Ihdr {
+            width: 1,
+            height: 1,
+            bit_depth: 8,
+            color_type,
+            compression_method: 0,
+            filter_method: 0,
+            interlace: Interlace::None,
+        }
+    }
+
+    fn violations(types: &[&str]) -> Vec<Violation> {
+        let chunks: Vec<Chunk> = types.iter().map(|t| chunk(t)).collect();
+        validate(&chunks)
+    }
+
+    fn indices(violations: &[Violation]) -> Vec<Option<usize>> {
+        violations.iter().map(|v| v.index).collect()
+    }
+
+    #[test]
+    fn test_valid_sequence() {
+        assert!(violations(&["IHDR", "gAMA", "PLTE", "tRNS", "IDAT", "IDAT", "tEXt", "IEND"]).is_empty());
+    }
+
+    #[test]
+    fn test_missing_critical_chunks() {
+        let found = violations(&["tEXt"]);
+        assert_eq!(indices(&found), [None, None, None]);
+    }
+
+    #[test]
+    fn test_ihdr_not_first() {
+        let found = violations(&["gAMA", "IHDR", "IDAT", "IEND"]);
+        assert_eq!(indices(&found), [Some(1)]);
+    }
+
+    #[test]
+    fn test_multiple_iend() {
+        let found = violations(&["IHDR", "IDAT", "IEND", "IEND"]);
+        assert_eq!(indices(&found), [Some(2), Some(3)]);
+    }
+
+    #[test]
+    fn test_plte_after_idat() {
+        let found = violations(&["IHDR", "IDAT", "PLTE", "IEND"]);
+        assert_eq!(indices(&found), [Some(2)]);
+    }
+
+    #[test]
+    fn test_non_contiguous_idat() {
+        let found = violations(&["IHDR", "IDAT", "tEXt", "IDAT", "IEND"]);
+        assert_eq!(indices(&found), [Some(3)]);
+    }
+
+    #[test]
+    fn test_ancillary_ordering() {
+        let found = violations(&["IHDR", "PLTE", "gAMA", "bKGD", "IDAT", "pHYs", "IEND"]);
+        assert_eq!(indices(&found), [Some(2), Some(5)]);
+    }
+
+    #[test]
+    fn test_unknown_critical_chunk() {
+        let found = violations(&["IHDR", "IDAT", "RuSt", "IEND"]);
+        assert_eq!(indices(&found), [Some(2)]);
+    }
+
+    #[test]
+    fn test_indexed_requires_plte() {
+        let mut chunks: Vec<Chunk> = ["IHDR", "IDAT", "IEND"].iter().map(|t| chunk(t)).collect();
+        chunks[0] = Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr(ColorType::Indexed).as_bytes());
+        let found = validate(&chunks);
+        assert_eq!(indices(&found), [None]);
+    }
+}
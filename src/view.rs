@@ -0,0 +1,280 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::PNGError;
+use crate::ihdr::Ihdr;
+use crate::png::Png;
+use crc::crc32::{self, Hasher32};
+
+/// A chunk borrowed from the buffer it was parsed from, such as a whole file or an mmap of it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkRef<'a> {
+    offset: usize,
+    chunk_type: [u8; 4],
+    data: &'a [u8],
+    stored_crc: u32,
+}
+
+impl<'a> ChunkRef<'a> {
+    /// Parses the chunk at the start of `value` without verifying its CRC
+    pub fn parse(value: &'a [u8]) -> Result<ChunkRef<'a>, PNGError> {
+        // [LENGTH][TYPE][--DATA--][CRC]
+        if value.len() < Chunk::METADATA_LENGTH {
+            return Err(PNGError::Truncated {
+                offset: 0,
+                needed: Chunk::METADATA_LENGTH,
+                available: value.len(),
+            });
+        }
+        let length = u32::from_be_bytes([value[0], value[1], value[2], value[3]]);
+        if length > Chunk::MAX_LENGTH {
+            return Err(PNGError::InvalidLength { offset: 0, length });
+        }
+        let length = length as usize;
+        if value.len() < length + Chunk::METADATA_LENGTH {
+            return Err(PNGError::Truncated {
+                offset: 0,
+                needed: length + Chunk::METADATA_LENGTH,
+                available: value.len(),
+            });
+        }
+        let chunk_type = [value[4], value[5], value[6], value[7]];
+        ChunkType::try_from(chunk_type).map_err(|e| e.offset_by(4))?;
+        let crc = &value[8 + length..12 + length];
+        Ok(ChunkRef {
+            offset: 0,
+            chunk_type,
+            data: &value[8..8 + length],
+            stored_crc: u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]]),
+        })
+    }
+
+    /// Byte offset of the chunk in the buffer it was parsed from
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn chunk_type(&self) -> ChunkType {
+        ChunkType::try_from(self.chunk_type).expect("chunk type was checked when parsing")
+    }
+
+    /// Whether this is a chunk of `chunk_type`, compared without building a `ChunkType`
+    pub fn is_type(&self, chunk_type: &str) -> bool {
+        self.chunk_type == chunk_type.as_bytes()
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn length(&self) -> usize {
+        self.data.len()
+    }
+
+    /// The CRC stored after the data, which `crc_ok` compares with the one computed from the contents
+    pub fn stored_crc(&self) -> u32 {
+        self.stored_crc
+    }
+
+    pub fn crc(&self) -> u32 {
+        let mut digest = crc32::Digest::new(crc32::IEEE);
+        digest.write(&self.chunk_type);
+        digest.write(self.data);
+        digest.sum32()
+    }
+
+    pub fn crc_ok(&self) -> bool {
+        self.stored_crc == self.crc()
+    }
+
+    /// Copies the chunk out of the buffer
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(self.chunk_type(), self.data.to_vec())
+    }
+}
+
+/// The chunk data, like `AsRef<[u8]>` for `Chunk`
+impl AsRef<[u8]> for ChunkRef<'_> {
+    fn as_ref(&self) -> &[u8] {
+        self.data
+    }
+}
+
+impl From<ChunkRef<'_>> for Chunk {
+    fn from(chunk: ChunkRef<'_>) -> Chunk {
+        chunk.to_chunk()
+    }
+}
+
+/// Iterates over the chunks of a file in a buffer without checking their CRCs, see `ChunkRefs::new`
+#[derive(Debug, Clone)]
+pub struct ChunkRefs<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    failed: bool,
+}
+
+impl<'a> ChunkRefs<'a> {
+    /// Checks the signature at the start of `bytes` and returns an iterator over the chunks after it
+    ///
+    /// A chunk that cannot be parsed yields an error and ends the iteration.
+    pub fn new(bytes: &'a [u8]) -> Result<ChunkRefs<'a>, PNGError> {
+        let signature = Png::STANDARD_HEADER.len();
+        if bytes.len() < signature {
+            return Err(PNGError::Truncated { offset: 0, needed: signature, available: bytes.len() });
+        }
+        if bytes[..signature] != Png::STANDARD_HEADER {
+            let mut found = [0; 8];
+            found.copy_from_slice(&bytes[..signature]);
+            return Err(PNGError::InvalidSignature(found));
+        }
+        Ok(ChunkRefs { bytes, offset: signature, failed: false })
+    }
+}
+
+impl<'a> Iterator for ChunkRefs<'a> {
+    type Item = Result<ChunkRef<'a>, PNGError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.offset >= self.bytes.len() {
+            return None;
+        }
+        let offset = self.offset;
+        match ChunkRef::parse(&self.bytes[offset..]) {
+            Ok(chunk) => {
+                self.offset += chunk.length() + Chunk::METADATA_LENGTH;
+                Some(Ok(ChunkRef { offset, ..chunk }))
+            }
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e.offset_by(offset)))
+            }
+        }
+    }
+}
+
+/// A PNG file borrowed from a buffer, checked once when created and read in place afterwards
+///
+/// Listing and searching chunks does not allocate. Use `to_png` to get an owned, editable `Png`.
+#[derive(Debug, Clone, Copy)]
+pub struct PngRef<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> PngRef<'a> {
+    /// The chunks of the file in order
+    pub fn chunks(&self) -> impl Iterator<Item = ChunkRef<'a>> + 'a {
+        // Every chunk parsed when the view was created, so there are no errors left to report
+        ChunkRefs { bytes: self.bytes, offset: Png::STANDARD_HEADER.len(), failed: false }.filter_map(Result::ok)
+    }
+
+    /// Returns every chunk of `chunk_type` in file order
+    ///
+    /// The chunks borrow the buffer, not `chunk_type`, so they outlive a temporary key.
+    pub fn chunks_by_type<'k>(&self, chunk_type: &'k str) -> impl Iterator<Item = ChunkRef<'a>> + 'k
+    where
+        'a: 'k,
+    {
+        self.chunks().filter(move |chunk| chunk.is_type(chunk_type))
+    }
+
+    /// Returns the first chunk of `chunk_type`
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<ChunkRef<'a>> {
+        self.chunks().find(|chunk| chunk.is_type(chunk_type))
+    }
+
+    /// Decodes the `IHDR` chunk, which the spec requires to come first
+    pub fn header(&self) -> Result<Ihdr, PNGError> {
+        match self.chunks().next() {
+            Some(chunk) => Ihdr::try_from(chunk),
+            None => Err(PNGError::MissingIhdr),
+        }
+    }
+
+    /// The whole file, signature included
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Copies every chunk out of the buffer
+    pub fn to_png(&self) -> Png {
+        Png::from_chunks(self.chunks().map(Chunk::from).collect())
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for PngRef<'a> {
+    type Error = PNGError;
+
+    /// Checks the signature, the structure and every CRC, like `Png::try_from`
+    fn try_from(bytes: &'a [u8]) -> Result<PngRef<'a>, PNGError> {
+        for chunk in ChunkRefs::new(bytes)? {
+            let chunk = chunk?;
+            if !chunk.crc_ok() {
+                return Err(PNGError::InvalidCrc { offset: chunk.offset, expected: chunk.crc(), actual: chunk.stored_crc });
+            }
+        }
+        Ok(PngRef { bytes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn file() -> Vec<u8> {
+        let chunk = |chunk_type: &str, data: &[u8]| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec());
+        let header = Ihdr::try_from(&[0, 0, 0, 4, 0, 0, 0, 4, 8, 2, 0, 0, 0][..]).unwrap();
+        Png::from_chunks(vec![
+            chunk("IHDR", &header.as_bytes()),
+            chunk("ruSt", b"first"),
+            chunk("IDAT", b"pixels"),
+            chunk("ruSt", b"second"),
+            chunk("IEND", b""),
+        ])
+        .as_bytes()
+    }
+
+    #[test]
+    fn test_views_borrow_the_buffer() {
+        let bytes = file();
+        let png = PngRef::try_from(&bytes[..]).unwrap();
+        let messages: Vec<&[u8]> = png.chunks_by_type("ruSt").map(|chunk| chunk.data()).collect();
+        assert_eq!(messages, [b"first".as_slice(), b"second"]);
+        // The data points into the original buffer rather than a copy
+        assert!(bytes.as_ptr_range().contains(&messages[0].as_ptr()));
+
+        // A key that only lives for the lookup does not shorten the life of the chunks
+        let found: Vec<ChunkRef> = {
+            let chunk_type = String::from("ruSt");
+            png.chunks_by_type(&chunk_type).collect()
+        };
+        assert_eq!(found[1].data(), b"second");
+
+        let idat = png.chunk_by_type("IDAT").unwrap();
+        assert_eq!(&bytes[idat.offset()..idat.offset() + 4], 6u32.to_be_bytes());
+        assert_eq!(png.header().unwrap().width, 4);
+    }
+
+    #[test]
+    fn test_matches_owned_parser() {
+        let bytes = file();
+        let owned = Png::try_from(&bytes[..]).unwrap();
+        let borrowed = PngRef::try_from(&bytes[..]).unwrap();
+        assert_eq!(borrowed.to_png().as_bytes(), owned.as_bytes());
+        for (chunk, owned) in borrowed.chunks().zip(owned.chunks()) {
+            assert_eq!(chunk.crc(), owned.crc());
+            assert_eq!(chunk.to_chunk().as_bytes(), owned.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_errors_match_owned_parser() {
+        let mut bytes = file();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        assert_eq!(PngRef::try_from(&bytes[..]).err(), Png::try_from(&bytes[..]).err());
+        for end in [0, 5, 20, bytes.len() - 3] {
+            assert_eq!(PngRef::try_from(&bytes[..end]).err(), Png::try_from(&bytes[..end]).err(), "prefix of {} bytes", end);
+        }
+    }
+}
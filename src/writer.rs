@@ -0,0 +1,160 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crc::crc32::{self, Hasher32};
+use std::io::{self, Write};
+
+/// Writes a PNG file chunk by chunk to any `Write`, the counterpart of `ChunkReader`
+///
+/// The signature goes out in front of the first chunk. Chunks are written straight to the
+/// underlying writer, so wrap files in a `BufWriter`. Call `finish` at the end, which also
+/// writes the signature of a file without chunks.
+pub struct ChunkWriter<W: Write> {
+    writer: W,
+    signature_written: bool,
+}
+
+impl<W: Write> ChunkWriter<W> {
+    pub fn new(writer: W) -> Self {
+        ChunkWriter { writer, signature_written: false }
+    }
+
+    /// Writes a whole chunk, failing before anything is written if its data is longer than `Chunk::MAX_LENGTH`
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> io::Result<()> {
+        if chunk.length() > Chunk::MAX_LENGTH as usize {
+            return Err(too_long());
+        }
+        self.write_signature()?;
+        chunk.write_to(&mut self.writer)
+    }
+
+    /// Starts a chunk of `length` bytes whose data is written piece by piece through the returned writer
+    ///
+    /// The CRC is computed as the data goes by and written by `ChunkData::finish`, which fails
+    /// if more or less than `length` bytes were written.
+    pub fn start_chunk(&mut self, chunk_type: &ChunkType, length: u32) -> io::Result<ChunkData<'_, W>> {
+        if length > Chunk::MAX_LENGTH {
+            return Err(too_long());
+        }
+        self.write_signature()?;
+        self.writer.write_all(&length.to_be_bytes())?;
+        self.writer.write_all(&chunk_type.bytes())?;
+        let mut digest = crc32::Digest::new(crc32::IEEE);
+        digest.write(&chunk_type.bytes());
+        Ok(ChunkData { writer: &mut self.writer, digest, remaining: length })
+    }
+
+    /// Flushes the file and returns the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_signature()?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_signature(&mut self) -> io::Result<()> {
+        if !self.signature_written {
+            self.writer.write_all(&Png::STANDARD_HEADER)?;
+            self.signature_written = true;
+        }
+        Ok(())
+    }
+}
+
+fn too_long() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "chunk length exceeds 2^31 - 1")
+}
+
+/// The data of a chunk started with `ChunkWriter::start_chunk`
+pub struct ChunkData<'a, W: Write> {
+    writer: &'a mut W,
+    digest: crc32::Digest,
+    /// Bytes still to come before the CRC
+    remaining: u32,
+}
+
+impl<W: Write> ChunkData<'_, W> {
+    /// Writes the CRC, completing the chunk
+    pub fn finish(self) -> io::Result<()> {
+        if self.remaining > 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("chunk data is {} bytes short of its declared length", self.remaining),
+            ));
+        }
+        self.writer.write_all(&self.digest.sum32().to_be_bytes())
+    }
+}
+
+impl<W: Write> Write for ChunkData<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.len() > self.remaining as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk data exceeds its declared length"));
+        }
+        let written = self.writer.write(buf)?;
+        self.digest.write(&buf[..written]);
+        self.remaining -= written as u32;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::ChunkReader;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_matches_as_bytes() {
+        let png = Png::from_chunks(vec![chunk("IHDR", &[0; 13]), chunk("ruSt", b"message"), chunk("IEND", b"")]);
+        let mut writer = ChunkWriter::new(Vec::new());
+        for chunk in png.chunks() {
+            writer.write_chunk(chunk).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), png.as_bytes());
+        assert_eq!(ChunkWriter::new(Vec::new()).finish().unwrap(), Png::STANDARD_HEADER);
+    }
+
+    #[test]
+    fn test_streamed_chunk() {
+        let pixels: Vec<u8> = (0..100_000u32).map(|i| i as u8).collect();
+        let mut writer = ChunkWriter::new(Vec::new());
+        let mut data = writer.start_chunk(&ChunkType::from_str("IDAT").unwrap(), pixels.len() as u32).unwrap();
+        for piece in pixels.chunks(999) {
+            data.write_all(piece).unwrap();
+        }
+        data.finish().unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let chunks: Vec<Chunk> = ChunkReader::new(&bytes[..]).collect::<Result<_, _>>().unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].data(), &pixels[..]);
+    }
+
+    #[test]
+    fn test_oversized_chunk_is_rejected() {
+        // Zeroed allocations are not touched until written, so this does not need 2 GiB of memory
+        let chunk = Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![0; Chunk::MAX_LENGTH as usize + 1]);
+        let mut writer = ChunkWriter::new(Vec::new());
+        assert_eq!(writer.write_chunk(&chunk).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(chunk.write_to(&mut Vec::new()).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(writer.finish().unwrap(), Png::STANDARD_HEADER);
+    }
+
+    #[test]
+    fn test_declared_length_is_enforced() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let mut writer = ChunkWriter::new(Vec::new());
+        let mut data = writer.start_chunk(&chunk_type, 4).unwrap();
+        assert!(data.write_all(b"too long").is_err());
+        data.write_all(b"abc").unwrap();
+        assert_eq!(data.finish().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}